@@ -1,39 +1,189 @@
+use std::env;
 use std::fs;
 use std::process;
 
+/// Finds every combination of `k` distinct entries of `nums` that add up to
+/// `target`. Each combination is returned as the ascending list of the
+/// indices of its entries in `nums`.
+///
+/// The entries are sorted once, then the search fixes the first `k - 2`
+/// entries and closes the last two with a two-pointer sweep, giving
+/// O(n^(k-1)) time instead of O(n^k).
+fn k_sum(nums: &[i64], k: usize, target: i64) -> Vec<Vec<usize>> {
+    let mut sorted: Vec<(i64, usize)> = nums.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+    sorted.sort();
+
+    let mut res = vec![];
+    if k == 0 || k > sorted.len() {
+        return res;
+    }
+    let mut picked = vec![];
+    search(&sorted, k, target as i128, &mut picked, &mut res);
+    for combination in res.iter_mut() {
+        combination.sort();
+    }
+    res
+}
+
+fn search(
+    sorted: &[(i64, usize)],
+    k: usize,
+    target: i128,
+    picked: &mut Vec<usize>,
+    res: &mut Vec<Vec<usize>>,
+) {
+    if sorted.len() < k {
+        return;
+    }
+    match k {
+        1 => {
+            for &(x, i) in sorted {
+                if x as i128 == target {
+                    let mut combination = picked.clone();
+                    combination.push(i);
+                    res.push(combination);
+                }
+            }
+        }
+        2 => two_sum(sorted, target, picked, res),
+        _ => {
+            for (n, &(x, i)) in sorted.iter().enumerate() {
+                // the remaining entries are at least x each, so once even
+                // the smallest completion overshoots nothing further can match
+                if x as i128 * k as i128 > target {
+                    break;
+                }
+                picked.push(i);
+                search(&sorted[n + 1..], k - 1, target - x as i128, picked, res);
+                picked.pop();
+            }
+        }
+    }
+}
+
+fn two_sum(sorted: &[(i64, usize)], target: i128, picked: &[usize], res: &mut Vec<Vec<usize>>) {
+    let mut lo = 0;
+    let mut hi = sorted.len() - 1;
+    while lo < hi {
+        let (a, b) = (sorted[lo].0, sorted[hi].0);
+        let sum = a as i128 + b as i128;
+        if sum < target {
+            lo += 1;
+        } else if sum > target {
+            hi -= 1;
+        } else if a == b {
+            // every pair inside the run of equal values matches
+            for i in lo..=hi {
+                for j in i + 1..=hi {
+                    res.push([picked, &[sorted[i].1, sorted[j].1]].concat());
+                }
+            }
+            break;
+        } else {
+            let lo_end = lo + sorted[lo..].iter().take_while(|e| e.0 == a).count();
+            let hi_start = hi + 1 - sorted[..=hi].iter().rev().take_while(|e| e.0 == b).count();
+            for i in lo..lo_end {
+                for j in hi_start..=hi {
+                    res.push([picked, &[sorted[i].1, sorted[j].1]].concat());
+                }
+            }
+            lo = lo_end;
+            if hi_start == 0 {
+                break;
+            }
+            hi = hi_start - 1;
+        }
+    }
+}
+
+fn parse_arg(args: &[String], n: usize, name: &str) -> Option<i64> {
+    args.get(n).map(|raw| {
+        raw.parse().unwrap_or_else(|err| {
+            println!("Invalid {} '{}'. {}", name, raw, err);
+            process::exit(1);
+        })
+    })
+}
+
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let k = parse_arg(&args, 1, "k");
+    let target = parse_arg(&args, 2, "target").unwrap_or(2020);
+    if k.is_some_and(|k| k < 1) {
+        println!("k must be at least 1");
+        process::exit(1);
+    }
+
     let raw = fs::read_to_string("input.txt").unwrap_or_else(|err| {
         println!("Failed to open file. {}", err);
         process::exit(1);
-    } );
-
-    let nums : Vec<i32> = raw.lines()
-        .map(|x| x.parse().unwrap())
-        .collect();
-  
-    let res : Vec<i32> = nums.iter()
-        .map(|&x| nums.iter().map(move |&y| (x, y)))
-        .flatten()
-        .filter(|(x, y)| x+y == 2020)
-        .map(|(x, y)| x*y)
-        .collect();
-
-    println!("x+y = 2020, x*y = {}", res.first().unwrap());
-    
-    let nx = nums.clone().into_iter();
-    let ny = nums.clone().into_iter();
-    let nz = nums.clone().into_iter();
-    let cube = nx.flat_map(|x| {
-        ny.clone().flat_map({
-            let nz = &nz;
-            move |y| nz.clone().map(move |z| (x, y, z))
-        })
     });
-        
-    let res : Vec<i32> = cube
-        .filter(|(x, y, z)| x+y+z == 2020)
-        .map(|(x, y, z)| x*y*z)
-        .collect();
 
-    println!("x+y+z = 2020, x*y*z = {}", res.first().unwrap());
+    let nums: Vec<i64> = raw.lines().map(|x| x.parse().unwrap()).collect();
+
+    let ks = match k {
+        Some(k) => vec![k as usize],
+        None => vec![2, 3],
+    };
+    for k in ks {
+        let combinations = k_sum(&nums, k, target);
+        println!(
+            "{} entries summing to {}: {} found",
+            k,
+            target,
+            combinations.len()
+        );
+        for combination in combinations {
+            let values: Vec<i64> = combination.iter().map(|&i| nums[i]).collect();
+            let product = values
+                .iter()
+                .try_fold(1i64, |acc, &x| acc.checked_mul(x))
+                .map_or("overflow".to_string(), |p| p.to_string());
+            println!(
+                "  indices {:?}, values {:?}, product = {}",
+                combination, values, product
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const EXAMPLE: [i64; 6] = [1721, 979, 366, 299, 675, 1456];
+
+    #[test]
+    fn example_pair_and_triple() {
+        assert_eq!(k_sum(&EXAMPLE, 2, 2020), vec![vec![0, 3]]);
+        assert_eq!(k_sum(&EXAMPLE, 3, 2020), vec![vec![1, 2, 4]]);
+    }
+
+    #[test]
+    fn every_combination_with_duplicates() {
+        let mut res = k_sum(&[5, 5, 5, 1, 9], 2, 10);
+        res.sort();
+        assert_eq!(res, vec![vec![0, 1], vec![0, 2], vec![1, 2], vec![3, 4]]);
+        let mut res = k_sum(&[2, 3, 3, 7, 7], 2, 10);
+        res.sort();
+        assert_eq!(res, vec![vec![1, 3], vec![1, 4], vec![2, 3], vec![2, 4]]);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let nums: Vec<i64> = (0..16).map(|i| (i * 37 % 23) - 5).collect();
+        for k in 1..=4 {
+            let mut brute = vec![];
+            for mask in 0u32..1 << nums.len() {
+                let idx: Vec<usize> = (0..nums.len()).filter(|i| mask >> i & 1 == 1).collect();
+                if idx.len() == k && idx.iter().map(|&i| nums[i]).sum::<i64>() == 12 {
+                    brute.push(idx);
+                }
+            }
+            let mut res = k_sum(&nums, k, 12);
+            res.sort();
+            brute.sort();
+            assert_eq!(res, brute);
+        }
+    }
 }
@@ -1,31 +1,206 @@
-fn main() {
-    let mut sled_valids = 0;
-    let mut toboggan_valids = 0;
-    for line in include_str!("../input0.txt").trim().split("\n") {
-        let (rule, pswd) = line.split_once(": ").unwrap();
-        let (range, letter) = rule.split_once(" ").unwrap();
+use std::env;
+use std::fmt;
+use std::process;
+
+struct Entry {
+    lo: usize,
+    hi: usize,
+    letter: char,
+    password: String,
+}
 
-        let letter = letter.chars().nth(0).unwrap();
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    msg: String,
+}
 
-        let (lo, hi) = range.split_once("-").unwrap();
-        let lo: i32 = lo.parse().unwrap();
-        let hi: i32 = hi.parse().unwrap();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
 
-        let count: i32 = pswd.chars().map(|c| (c == letter) as i32).sum();
-        if lo <= count && count <= hi {
-            sled_valids += 1;
+impl Entry {
+    fn from_line(line: &str) -> Result<Self, String> {
+        let (rule, password) = line
+            .split_once(": ")
+            .ok_or("expected '<rule>: <password>'")?;
+        let (range, letter) = rule
+            .split_once(' ')
+            .ok_or("expected '<lo>-<hi> <letter>'")?;
+        let mut chars = letter.chars();
+        let letter = match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            _ => return Err(format!("expected a single letter, found '{}'", letter)),
+        };
+        let (lo, hi) = range
+            .split_once('-')
+            .ok_or(format!("expected a range '<lo>-<hi>', found '{}'", range))?;
+        let lo: usize = lo
+            .parse()
+            .map_err(|err| format!("invalid lower bound '{}': {}", lo, err))?;
+        let hi: usize = hi
+            .parse()
+            .map_err(|err| format!("invalid upper bound '{}': {}", hi, err))?;
+        if lo > hi {
+            return Err(format!("empty range {}-{}", lo, hi));
         }
+        Ok(Entry {
+            lo,
+            hi,
+            letter,
+            password: password.to_string(),
+        })
+    }
+}
+
+fn parse(raw: &str) -> Result<Vec<Entry>, ParseError> {
+    raw.trim()
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| Entry::from_line(line).map_err(|msg| ParseError { line: i + 1, msg }))
+        .collect()
+}
+
+trait PasswordPolicy {
+    fn name(&self) -> &'static str;
 
-        let tobo: i32 = pswd
+    /// Checks the password against the rule of its entry, explaining why it
+    /// is rejected.
+    fn check(&self, entry: &Entry) -> Result<(), String>;
+}
+
+/// The letter must appear between lo and hi times.
+struct SledPolicy;
+
+/// The letter must appear at exactly one of the 1-based positions lo and hi.
+struct TobogganPolicy;
+
+impl PasswordPolicy for SledPolicy {
+    fn name(&self) -> &'static str {
+        "sled"
+    }
+
+    fn check(&self, entry: &Entry) -> Result<(), String> {
+        let count = entry
+            .password
             .chars()
-            .enumerate()
-            .map(|(i, c)| (i as i32 + 1, c))
-            .map(|(i, c)| (c == letter && (hi == i || lo == i)) as i32)
-            .sum();
-        if tobo == 1 {
-            toboggan_valids += 1;
+            .filter(|&c| c == entry.letter)
+            .count();
+        if entry.lo <= count && count <= entry.hi {
+            Ok(())
+        } else {
+            Err(format!(
+                "letter '{}' appears {} times, allowed {}-{}",
+                entry.letter, count, entry.lo, entry.hi
+            ))
+        }
+    }
+}
+
+impl PasswordPolicy for TobogganPolicy {
+    fn name(&self) -> &'static str {
+        "toboggan"
+    }
+
+    fn check(&self, entry: &Entry) -> Result<(), String> {
+        let at = |pos: usize| pos > 0 && entry.password.chars().nth(pos - 1) == Some(entry.letter);
+        match (at(entry.lo), at(entry.hi)) {
+            (true, false) | (false, true) => Ok(()),
+            (true, true) => Err(format!(
+                "letter '{}' is at both positions {} and {}",
+                entry.letter, entry.lo, entry.hi
+            )),
+            (false, false) => Err(format!(
+                "letter '{}' is at neither position {} nor {}",
+                entry.letter, entry.lo, entry.hi
+            )),
         }
     }
-    println!("sled     valid passwords: {}", sled_valids);
-    println!("toboggan valid passwords: {}", toboggan_valids);
+}
+
+fn policies() -> Vec<Box<dyn PasswordPolicy>> {
+    vec![Box::new(SledPolicy), Box::new(TobogganPolicy)]
+}
+
+fn count_valid(entries: &[Entry], policy: &dyn PasswordPolicy) -> usize {
+    entries.iter().filter(|e| policy.check(e).is_ok()).count()
+}
+
+fn report(entries: &[Entry], policies: &[Box<dyn PasswordPolicy>]) {
+    for (i, entry) in entries.iter().enumerate() {
+        let failures: Vec<String> = policies
+            .iter()
+            .filter_map(|p| {
+                p.check(entry)
+                    .err()
+                    .map(|why| format!("{}: {}", p.name(), why))
+            })
+            .collect();
+        if failures.is_empty() {
+            println!("line {} '{}': ok", i + 1, entry.password);
+        } else {
+            println!(
+                "line {} '{}': {}",
+                i + 1,
+                entry.password,
+                failures.join("; ")
+            );
+        }
+    }
+}
+
+fn main() {
+    let entries = parse(include_str!("../input0.txt")).unwrap_or_else(|err| {
+        println!("Failed to parse input. {}", err);
+        process::exit(1);
+    });
+    let policies = policies();
+
+    if env::args().nth(1).as_deref() == Some("report") {
+        report(&entries, &policies);
+        return;
+    }
+
+    for policy in policies.iter() {
+        println!(
+            "{:<8} valid passwords: {}",
+            policy.name(),
+            count_valid(&entries, policy.as_ref())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn small_input() {
+        let entries = parse(include_str!("../test0.txt")).unwrap();
+        assert_eq!(count_valid(&entries, &SledPolicy), 2);
+        assert_eq!(count_valid(&entries, &TobogganPolicy), 1);
+    }
+
+    #[test]
+    fn violation_reasons() {
+        let entries = parse(include_str!("../test0.txt")).unwrap();
+        assert_eq!(
+            SledPolicy.check(&entries[1]),
+            Err("letter 'b' appears 0 times, allowed 1-3".to_string())
+        );
+        assert_eq!(
+            TobogganPolicy.check(&entries[2]),
+            Err("letter 'c' is at both positions 2 and 9".to_string())
+        );
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let err = parse("1-3 a: abcde\n1-x b: cdefg").err().unwrap();
+        assert_eq!(err.line, 2);
+        assert!(parse("1-3 a abcde").is_err());
+        assert!(parse("3-1 a: abcde").is_err());
+    }
 }
@@ -1,74 +1,220 @@
-fn slide(lines: Vec<Vec<char>>, vx: i32, vy: i32) -> i32 {
-    let mut x: i32 = 0;
-    let mut y: i32 = 0;
-    let mut hits: i32 = 0;
-    loop {
-        if lines[y as usize][x as usize] == '#' {
-            hits += 1;
+use std::env;
+use std::fs;
+use std::process;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Slope {
+    right: i64,
+    down: usize,
+}
+
+impl Slope {
+    fn from_string(raw: &str) -> Result<Self, String> {
+        let (right, down) = raw
+            .trim()
+            .split_once(',')
+            .ok_or(format!("expected '<right>,<down>', found '{}'", raw))?;
+        let right = right
+            .trim()
+            .parse()
+            .map_err(|err| format!("invalid right step '{}': {}", right, err))?;
+        let down = down
+            .trim()
+            .parse()
+            .map_err(|err| format!("invalid down step '{}': {}", down, err))?;
+        if down == 0 {
+            return Err(format!("slope '{}' never moves down", raw));
         }
-        x += vx;
-        y += vy;
-        if y as usize >= lines.len() {
+        Ok(Slope { right, down })
+    }
+}
+
+const DEFAULT_SLOPES: [Slope; 5] = [
+    Slope { right: 1, down: 1 },
+    Slope { right: 3, down: 1 },
+    Slope { right: 5, down: 1 },
+    Slope { right: 7, down: 1 },
+    Slope { right: 1, down: 2 },
+];
+
+/// Lists the squares visited going down the map from the top left corner.
+/// Without wrapping the slide stops as soon as it leaves the map sideways.
+/// An empty row has nothing to wrap around, so the slide stops there too.
+fn trajectory(lines: &[Vec<char>], slope: Slope, wrap: bool) -> Vec<(usize, usize)> {
+    let mut visited = vec![];
+    let mut x: i64 = 0;
+    let mut y: usize = 0;
+    while y < lines.len() {
+        let width = lines[y].len() as i64;
+        if width == 0 {
+            break;
+        } else if wrap {
+            x = x.rem_euclid(width);
+        } else if x < 0 || x >= width {
             break;
         }
-        if x as usize >= lines[y as usize].len() {
-            x -= lines[y as usize].len() as i32;
-        }
+        visited.push((x as usize, y));
+        x += slope.right;
+        y += slope.down;
     }
-    hits
+    visited
+}
+
+fn slide(lines: &[Vec<char>], slope: Slope, wrap: bool) -> usize {
+    trajectory(lines, slope, wrap)
+        .into_iter()
+        .filter(|&(x, y)| lines[y][x] == '#')
+        .count()
 }
 
-fn slide_everywhere(lines: Vec<Vec<char>>) -> i32 {
-    let mut hits: i32 = 1;
-    for (vx, vy) in vec![(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)] {
-        hits *= slide(lines.clone(), vx, vy);
+fn slide_everywhere(lines: &[Vec<char>], slopes: &[Slope], wrap: bool) -> usize {
+    slopes
+        .iter()
+        .map(|&slope| slide(lines, slope, wrap))
+        .product()
+}
+
+/// Draws the map with the trajectory marked as `X` on trees and `O` on
+/// clear squares.
+fn render(lines: &[Vec<char>], slope: Slope, wrap: bool) -> String {
+    let mut map = lines.to_vec();
+    for (x, y) in trajectory(lines, slope, wrap) {
+        map[y][x] = if lines[y][x] == '#' { 'X' } else { 'O' };
     }
-    hits
+    map.iter()
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn parse_slopes(raw: &str) -> Result<Vec<Slope>, String> {
+    raw.split_whitespace().map(Slope::from_string).collect()
 }
 
 fn main() {
+    let mut wrap = true;
+    let mut render_map = false;
+    let mut slopes = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--no-wrap" => {
+                wrap = false;
+                continue;
+            }
+            "--render" => {
+                render_map = true;
+                continue;
+            }
+            "--slopes" => {
+                let path = args.next().unwrap_or_else(|| {
+                    println!("--slopes needs a file");
+                    process::exit(1);
+                });
+                fs::read_to_string(&path)
+                    .map_err(|err| format!("failed to open '{}'. {}", path, err))
+                    .and_then(|raw| parse_slopes(&raw))
+            }
+            _ => parse_slopes(&arg),
+        };
+        slopes.extend(parsed.unwrap_or_else(|err| {
+            println!("Bad slope: {}", err);
+            process::exit(1);
+        }));
+    }
+    if slopes.is_empty() {
+        slopes = DEFAULT_SLOPES.to_vec();
+    }
+
     let lines: Vec<Vec<char>> = include_str!("../input0.txt")
         .trim()
-        .split("\n")
+        .split('\n')
         .map(|s| s.chars().collect())
         .collect();
-    let hits = slide(lines.clone(), 3, 1);
-    println!("hits: {}", hits);
-    let hits_everything = slide_everywhere(lines.clone());
-    println!("hits on the five paths: {}", hits_everything);
+
+    if render_map {
+        for &slope in slopes.iter() {
+            println!("right {}, down {}:", slope.right, slope.down);
+            println!("{}\n", render(&lines, slope, wrap));
+        }
+    }
+
+    println!("right  down  hits");
+    for &slope in slopes.iter() {
+        println!(
+            "{:>5}  {:>4}  {:>4}",
+            slope.right,
+            slope.down,
+            slide(&lines, slope, wrap)
+        );
+    }
+    println!(
+        "hits multiplied over the {} paths: {}",
+        slopes.len(),
+        slide_everywhere(&lines, &slopes, wrap)
+    );
 }
 
 #[cfg(test)]
 mod tests {
     use crate::*;
 
-    #[test]
-    fn small_input() {
-        let lines: Vec<Vec<char>> = include_str!("../test0.txt")
+    fn small_map() -> Vec<Vec<char>> {
+        include_str!("../test0.txt")
             .trim()
-            .split("\n")
+            .split('\n')
             .map(|s| s.chars().collect())
-            .collect();
-        assert_eq!(slide(lines, 3, 1), 7);
+            .collect()
+    }
+
+    #[test]
+    fn small_input() {
+        let slope = Slope { right: 3, down: 1 };
+        assert_eq!(slide(&small_map(), slope, true), 7);
     }
 
     #[test]
     fn big_input() {
         let lines: Vec<Vec<char>> = include_str!("../input0.txt")
             .trim()
-            .split("\n")
+            .split('\n')
             .map(|s| s.chars().collect())
             .collect();
-        assert_eq!(slide(lines, 3, 1), 220);
+        assert_eq!(slide(&lines, Slope { right: 3, down: 1 }, true), 220);
     }
 
     #[test]
     fn small_input_all_slopes() {
-        let lines: Vec<Vec<char>> = include_str!("../test0.txt")
-            .trim()
-            .split("\n")
-            .map(|s| s.chars().collect())
-            .collect();
-        assert_eq!(slide_everywhere(lines), 336);
+        assert_eq!(slide_everywhere(&small_map(), &DEFAULT_SLOPES, true), 336);
+    }
+
+    #[test]
+    fn no_wrap_stops_at_the_edge() {
+        let lines = small_map();
+        let slope = Slope { right: 3, down: 1 };
+        assert_eq!(trajectory(&lines, slope, false).len(), 4);
+        assert_eq!(slide(&lines, slope, false), 1);
+        assert_eq!(
+            render(&lines[..2], slope, false),
+            "O.##.......\n#..O#...#.."
+        );
+
+        let mut lines = small_map();
+        lines[4].clear();
+        assert_eq!(trajectory(&lines, slope, true).len(), 4);
+        assert_eq!(trajectory(&[vec![]], slope, false), vec![]);
+    }
+
+    #[test]
+    fn slope_parsing() {
+        assert_eq!(
+            parse_slopes("3,1 1,2"),
+            Ok(vec![
+                Slope { right: 3, down: 1 },
+                Slope { right: 1, down: 2 }
+            ])
+        );
+        assert!(parse_slopes("3,0").is_err());
+        assert!(parse_slopes("3").is_err());
     }
 }
@@ -1,130 +1,435 @@
+use std::env;
+use std::fmt;
 use std::fs;
+use std::str::FromStr;
 
-const VALID_CHARS: &str = "0123456789abcdef";
+/// The shape a field value must have to be valid.
+enum Kind {
+    /// A number with exactly `digits` digits between `min` and `max`.
+    Number { digits: usize, min: u32, max: u32 },
+    /// A height in centimetres or inches, each unit with its own
+    /// inclusive `(min, max)` range.
+    Height { cm: (u32, u32), inch: (u32, u32) },
+    /// A fixed prefix followed by exactly `len` characters from `class`.
+    Chars {
+        prefix: &'static str,
+        len: usize,
+        class: &'static str,
+    },
+    /// One of a fixed set of values.
+    OneOf(&'static [&'static str]),
+    /// Anything goes.
+    Any,
+}
+
+struct Field {
+    key: &'static str,
+    required: bool,
+    kind: Kind,
+}
+
+const DIGITS: &str = "0123456789";
+const HEX_DIGITS: &str = "0123456789abcdef";
+
+const SCHEMA: [Field; 8] = [
+    Field {
+        key: "byr",
+        required: true,
+        kind: Kind::Number {
+            digits: 4,
+            min: 1920,
+            max: 2002,
+        },
+    },
+    Field {
+        key: "iyr",
+        required: true,
+        kind: Kind::Number {
+            digits: 4,
+            min: 2010,
+            max: 2020,
+        },
+    },
+    Field {
+        key: "eyr",
+        required: true,
+        kind: Kind::Number {
+            digits: 4,
+            min: 2020,
+            max: 2030,
+        },
+    },
+    Field {
+        key: "hgt",
+        required: true,
+        kind: Kind::Height {
+            cm: (150, 193),
+            inch: (59, 76),
+        },
+    },
+    Field {
+        key: "hcl",
+        required: true,
+        kind: Kind::Chars {
+            prefix: "#",
+            len: 6,
+            class: HEX_DIGITS,
+        },
+    },
+    Field {
+        key: "ecl",
+        required: true,
+        kind: Kind::OneOf(&["amb", "blu", "brn", "gry", "grn", "hzl", "oth"]),
+    },
+    Field {
+        key: "pid",
+        required: true,
+        kind: Kind::Chars {
+            prefix: "",
+            len: 9,
+            class: DIGITS,
+        },
+    },
+    Field {
+        key: "cid",
+        required: false,
+        kind: Kind::Any,
+    },
+];
+
+impl Kind {
+    /// Checks a value, describing the rule it breaks.
+    fn check(&self, value: &str) -> Result<(), String> {
+        match self {
+            Kind::Number { digits, min, max } => check_number(value, *digits, *min, *max),
+            Kind::Height { cm, inch } => value.parse::<Height>()?.check(*cm, *inch),
+            Kind::Chars { prefix, len, class } => {
+                let rest = value
+                    .strip_prefix(prefix)
+                    .ok_or(format!("must start with '{}'", prefix))?;
+                if rest.chars().count() != *len || !rest.chars().all(|c| class.contains(c)) {
+                    return Err(format!(
+                        "must be '{}' followed by {} characters among [{}]",
+                        prefix, len, class
+                    ));
+                }
+                Ok(())
+            }
+            Kind::OneOf(values) => {
+                if values.contains(&value) {
+                    Ok(())
+                } else {
+                    Err(format!("must be one of {}", values.join(", ")))
+                }
+            }
+            Kind::Any => Ok(()),
+        }
+    }
+}
+
+/// Checks a decimal number with exactly `digits` digits that lies between
+/// `min` and `max`.
+fn check_number(value: &str, digits: usize, min: u32, max: u32) -> Result<(), String> {
+    let range = || format!("must be between {} and {}", min, max);
+    if value.is_empty() || !value.chars().all(|c| DIGITS.contains(c)) {
+        return Err(format!("'{}' is not a number, {}", value, range()));
+    }
+    if value.len() != digits {
+        return Err(format!("must have exactly {} digits", digits));
+    }
+    match value.parse::<u32>() {
+        Ok(num) if min <= num && num <= max => Ok(()),
+        _ => Err(range()),
+    }
+}
+
+/// A height kept in the unit it was written in, so that each unit is
+/// checked against its own range without any conversion.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Height {
+    Cm(u32),
+    In(u32),
+}
+
+impl FromStr for Height {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (num, height): (&str, fn(u32) -> Height) = if let Some(num) = value.strip_suffix("cm") {
+            (num, Height::Cm)
+        } else if let Some(num) = value.strip_suffix("in") {
+            (num, Height::In)
+        } else {
+            return Err(format!("'{}' must end with a unit among cm, in", value));
+        };
+        if num.is_empty() || !num.chars().all(|c| DIGITS.contains(c)) {
+            return Err(format!("'{}' is not a number", num));
+        }
+        num.parse()
+            .map(height)
+            .map_err(|err| format!("'{}' is not a number: {}", num, err))
+    }
+}
+
+impl Height {
+    /// Checks the height against the range of its own unit.
+    fn check(&self, cm: (u32, u32), inch: (u32, u32)) -> Result<(), String> {
+        let (num, (min, max), unit) = match *self {
+            Height::Cm(num) => (num, cm, "cm"),
+            Height::In(num) => (num, inch, "in"),
+        };
+        if min <= num && num <= max {
+            Ok(())
+        } else {
+            Err(format!(
+                "must be between {}{} and {}{}",
+                min, unit, max, unit
+            ))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Problem {
+    Missing,
+    Unknown,
+    Duplicate,
+    Malformed,
+    Invalid(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct Violation {
+    field: String,
+    problem: Problem,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.problem {
+            Problem::Missing => write!(f, "{}: required field is missing", self.field),
+            Problem::Unknown => write!(f, "{}: unknown field", self.field),
+            Problem::Duplicate => write!(f, "{}: field given more than once", self.field),
+            Problem::Malformed => write!(f, "'{}': expected '<key>:<value>'", self.field),
+            Problem::Invalid(rule) => write!(f, "{}: {}", self.field, rule),
+        }
+    }
+}
 
 #[derive(Default, Debug)]
 struct Passport {
-    birthday_year: Option<i32>,
-    issue_year: Option<i32>,
-    expiration_date: Option<i32>,
-    height: Option<i32>,
-    hair_color: Option<String>,
-    eye_color: Option<String>,
-    passport_id: Option<String>,
-    country_id: Option<String>,
+    fields: Vec<(String, String)>,
+    malformed: Vec<String>,
 }
 
 impl Passport {
-    fn from_map(map: &Vec<String>) -> Self {
+    /// Collects the `key:value` pairs of a batch entry, keeping aside the
+    /// pairs that cannot be split so they show up in the report.
+    fn from_map(map: &[String]) -> Self {
         let mut ret = Self::default();
         for pair in map {
-            let (key, value) = pair.split_once(":").unwrap();
-            match key {
-                "byr" => {
-                    ret.birthday_year = Some(value.parse().unwrap());
-                }
-                "iyr" => {
-                    ret.issue_year = Some(value.parse().unwrap());
-                }
-                "eyr" => {
-                    ret.expiration_date = Some(value.parse().unwrap());
-                }
-                "hgt" => {
-                    if let Some(num) = value.strip_suffix("cm") {
-                        ret.height = Some(num.parse().unwrap());
-                    } else if let Some(num) = value.strip_suffix("in") {
-                        ret.height = Some((num.parse::<f32>().unwrap() * 2.5399986284) as i32);
-                    } else {
-                        ret.height = Some(value.parse().unwrap());
-                    }
-                }
-                "hcl" => {
-                    ret.hair_color = Some(value.to_string());
-                }
-                "ecl" => {
-                    ret.eye_color = Some(value.to_string());
-                }
-                "pid" => {
-                    ret.passport_id = Some(value.to_string());
-                }
-                "cid" => {
-                    ret.country_id = Some(value.to_string());
-                }
-                _ => panic!(),
+            match pair.split_once(':') {
+                Some((key, value)) => ret.fields.push((key.to_string(), value.to_string())),
+                None => ret.malformed.push(pair.to_string()),
             }
         }
         ret
     }
 
+    fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
     fn check_presence(&self) -> bool {
-        self.birthday_year.is_some()
-            && self.issue_year.is_some()
-            && self.expiration_date.is_some()
-            && self.height.is_some()
-            && self.hair_color.is_some()
-            && self.eye_color.is_some()
-            && self.passport_id.is_some()
+        SCHEMA
+            .iter()
+            .all(|field| !field.required || self.get(field.key).is_some())
     }
 
-    fn check_value(&self) -> bool {
-        let mut valid = true;
-        if let Some(byr) = self.birthday_year {
-            valid &= byr >= 1920 && byr <= 2002;
-        }
-        if let Some(iyr) = self.issue_year {
-            valid &= iyr >= 2010 && iyr <= 2020;
-        }
-        if let Some(eyr) = self.expiration_date {
-            valid &= eyr >= 2020 && eyr <= 2030;
+    fn violations(&self) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = self
+            .malformed
+            .iter()
+            .map(|pair| Violation {
+                field: pair.clone(),
+                problem: Problem::Malformed,
+            })
+            .collect();
+        for (i, (key, _)) in self.fields.iter().enumerate() {
+            let problem = if !SCHEMA.iter().any(|field| field.key == key) {
+                Problem::Unknown
+            } else if self.fields[..i].iter().any(|(k, _)| k == key) {
+                Problem::Duplicate
+            } else {
+                continue;
+            };
+            violations.push(Violation {
+                field: key.clone(),
+                problem,
+            });
         }
-        if let Some(height) = self.height {
-            valid &= height >= 150 && height <= 193;
+        for field in SCHEMA.iter() {
+            let problem = match self.get(field.key) {
+                None if field.required => Problem::Missing,
+                None => continue,
+                Some(value) => match field.kind.check(value) {
+                    Ok(()) => continue,
+                    Err(rule) => Problem::Invalid(rule),
+                },
+            };
+            violations.push(Violation {
+                field: field.key.to_string(),
+                problem,
+            });
         }
-        if let Some(hair) = &self.hair_color {
-            valid &= hair.len() == 7;
-            valid &= hair.chars().next().unwrap() == '#';
-            valid &= hair
-                .chars()
-                .skip(1)
-                .find(|c| !VALID_CHARS.contains(*c))
-                .is_none();
-        }
-        if let Some(ecl) = &self.eye_color {
-            valid &= ecl == "amb"
-                || ecl == "blu"
-                || ecl == "brn"
-                || ecl == "gry"
-                || ecl == "grn"
-                || ecl == "hzl"
-                || ecl == "oth";
-        }
-        if let Some(pid) = &self.passport_id {
-            valid &= pid.chars().find(|c| !"0123456789".contains(*c)).is_none();
-            valid &= pid.chars().rev().skip(9).find(|c| *c != '0').is_none();
-        }
-        valid
+        violations
+    }
+
+    fn check_value(&self) -> bool {
+        self.violations().is_empty()
     }
 }
 
+fn parse(raw: &str) -> Vec<Passport> {
+    raw.split("\n\n")
+        .filter(|pass| !pass.trim().is_empty())
+        .map(|pass| {
+            let map = pass
+                .split_whitespace()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>();
+            Passport::from_map(&map)
+        })
+        .collect()
+}
+
 fn main() {
     let raw = fs::read_to_string("input.txt").unwrap();
+    let passports = parse(&raw);
 
-    let mut valid_presence = 0;
-    let mut valid_values = 0;
-
-    for pass in raw.split("\n\n") {
-        let map = pass
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-        let passport = Passport::from_map(&map);
-        if passport.check_presence() {
-            valid_presence += 1;
-            if passport.check_value() {
-                valid_values += 1;
+    if env::args().nth(1).as_deref() == Some("report") {
+        for (i, passport) in passports.iter().enumerate() {
+            let violations = passport.violations();
+            if violations.is_empty() {
+                println!("passport {}: valid", i + 1);
+            }
+            for violation in violations {
+                println!("passport {}: {}", i + 1, violation);
             }
         }
+        return;
     }
+
+    let valid_presence = passports.iter().filter(|p| p.check_presence()).count();
+    let valid_values = passports.iter().filter(|p| p.check_value()).count();
     println!("Passports with all field present: {}", valid_presence);
     println!("Passports with valid values: {}", valid_values);
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const INVALID: &str = "eyr:1972 cid:100
+hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+iyr:2019
+hcl:#602927 eyr:1967 hgt:170cm
+ecl:grn pid:012533040 byr:1946
+
+hcl:dab227 iyr:2012
+ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
+
+hgt:59cm ecl:zzz
+eyr:2038 hcl:74454a iyr:2023
+pid:3556412378 byr:2007";
+
+    const VALID: &str = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+eyr:2029 ecl:blu cid:129 byr:1989
+iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm
+
+hcl:#888785
+hgt:164cm byr:2001 iyr:2015 cid:88
+pid:545766238 ecl:hzl
+eyr:2022
+
+iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
+
+    #[test]
+    fn example_passports() {
+        assert!(parse(INVALID).iter().all(|p| !p.check_value()));
+        assert!(parse(VALID).iter().all(|p| p.check_value()));
+    }
+
+    #[test]
+    fn structured_violations() {
+        let passport = &parse("hgt:170 byr:1926 foo:bar iyr iyr:2018 iyr:2019")[0];
+        let violations = passport.violations();
+        assert!(violations.contains(&Violation {
+            field: "iyr".to_string(),
+            problem: Problem::Malformed
+        }));
+        assert!(violations.contains(&Violation {
+            field: "foo".to_string(),
+            problem: Problem::Unknown
+        }));
+        assert!(violations.contains(&Violation {
+            field: "iyr".to_string(),
+            problem: Problem::Duplicate
+        }));
+        assert!(violations.contains(&Violation {
+            field: "eyr".to_string(),
+            problem: Problem::Missing
+        }));
+        assert!(violations
+            .iter()
+            .any(|v| v.field == "hgt" && matches!(v.problem, Problem::Invalid(_))));
+        assert!(!violations.iter().any(|v| v.field == "byr"));
+    }
+
+    #[test]
+    fn height_parsing() {
+        assert_eq!("60in".parse(), Ok(Height::In(60)));
+        assert_eq!("190cm".parse(), Ok(Height::Cm(190)));
+        assert!("190".parse::<Height>().is_err());
+        assert!("cm".parse::<Height>().is_err());
+        assert!("-5in".parse::<Height>().is_err());
+        assert!("60ft".parse::<Height>().is_err());
+    }
+
+    #[test]
+    fn height_boundaries() {
+        let hgt = SCHEMA.iter().find(|field| field.key == "hgt").unwrap();
+        let valid = |raw: &str| hgt.kind.check(raw).is_ok();
+        assert!(!valid("149cm"));
+        assert!(valid("150cm"));
+        assert!(valid("193cm"));
+        assert!(!valid("194cm"));
+        assert!(!valid("58in"));
+        assert!(valid("59in"));
+        assert!(valid("76in"));
+        assert!(!valid("77in"));
+        // the same numbers in the other unit fall out of range
+        assert!(!valid("59cm"));
+        assert!(!valid("76cm"));
+        assert!(!valid("150in"));
+        assert!(!valid("170"));
+
+        let kind = Kind::Height {
+            cm: (100, 110),
+            inch: (40, 45),
+        };
+        assert!(kind.check("105cm").is_ok());
+        assert!(kind.check("150cm").is_err());
+        assert!(kind.check("40in").is_ok());
+        assert_eq!(
+            Height::In(46).check((100, 110), (40, 45)),
+            Err("must be between 40in and 45in".to_string())
+        );
+    }
+}
@@ -1,58 +1,209 @@
+use std::env;
 use std::fs;
+use std::ops::Range;
+use std::process;
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 struct Seat {
-    x: i32,
-    y: i32,
+    x: u32,
+    y: u32,
 }
 
-impl Seat {
-    fn from_string(raw: String) -> Self {
-        let (row, column) = raw.split_at(7);
+/// The free seat ids of a plane. The runs before the first and after the
+/// last occupied seat are kept as ranges, apart from the free seats in
+/// between, which are the candidates for a passenger's own seat.
+#[derive(Debug, PartialEq)]
+struct Missing {
+    front: Range<u32>,
+    gaps: Vec<u32>,
+    back: Range<u32>,
+}
+
+/// The layout of a plane: a boarding pass has `row_bits` F/B characters
+/// followed by `col_bits` L/R characters.
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    row_bits: u32,
+    col_bits: u32,
+}
+
+const DEFAULT_PLANE: Plane = Plane {
+    row_bits: 7,
+    col_bits: 3,
+};
+
+impl Plane {
+    fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    fn cols(&self) -> u32 {
+        1 << self.col_bits
+    }
+
+    fn decode(&self, raw: &str) -> Result<Seat, String> {
+        let len = (self.row_bits + self.col_bits) as usize;
+        let chars: Vec<char> = raw.chars().collect();
+        if chars.len() != len {
+            return Err(format!("'{}' should be {} characters long", raw, len));
+        }
+        let (row, column) = chars.split_at(self.row_bits as usize);
+        if let Some(c) = row.iter().find(|c| !"FB".contains(**c)) {
+            return Err(format!("'{}' has '{}' in the row part", raw, c));
+        }
+        if let Some(c) = column.iter().find(|c| !"LR".contains(**c)) {
+            return Err(format!("'{}' has '{}' in the column part", raw, c));
+        }
+        Ok(Seat {
+            x: decode(column.iter().collect(), 'R') as u32,
+            y: decode(row.iter().collect(), 'B') as u32,
+        })
+    }
+
+    fn encode(&self, seat: Seat) -> String {
+        encode(seat.y, self.row_bits, 'F', 'B') + &encode(seat.x, self.col_bits, 'L', 'R')
+    }
+
+    fn id(&self, seat: Seat) -> u32 {
+        seat.x + seat.y * self.cols()
+    }
+
+    fn seat(&self, id: u32) -> Seat {
         Seat {
-            x: decode(column.to_string(), 'R'),
-            y: decode(row.to_string(), 'B'),
+            x: id % self.cols(),
+            y: id / self.cols(),
+        }
+    }
+
+    /// Finds every free seat, walking the gaps between the sorted occupied
+    /// ids so that large planes need no memory beyond the seats given.
+    fn missing(&self, seats: &[Seat]) -> Missing {
+        let total = self.rows() * self.cols();
+        let mut ids: Vec<u32> = seats.iter().map(|&seat| self.id(seat)).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let (Some(&first), Some(&last)) = (ids.first(), ids.last()) else {
+            return Missing {
+                front: 0..total,
+                gaps: vec![],
+                back: total..total,
+            };
+        };
+        Missing {
+            front: 0..first,
+            gaps: ids
+                .windows(2)
+                .flat_map(|pair| pair[0] + 1..pair[1])
+                .collect(),
+            back: last + 1..total,
         }
     }
 
-    fn id(&self) -> i32 {
-        self.x + self.y * 8
+    /// Draws one line per row, `#` for occupied seats and `.` for free ones.
+    fn render(&self, seats: &[Seat]) -> String {
+        let mut map = vec![vec!['.'; self.cols() as usize]; self.rows() as usize];
+        for &seat in seats {
+            map[seat.y as usize][seat.x as usize] = '#';
+        }
+        let width = (self.rows() - 1).to_string().len();
+        map.iter()
+            .enumerate()
+            .map(|(y, row)| format!("{:>width$} {}", y, row.iter().collect::<String>()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+fn parse_bits(args: &mut impl Iterator<Item = String>, name: &str) -> u32 {
+    let raw = args.next().unwrap_or_default();
+    match raw.parse() {
+        Ok(bits) if (1..16).contains(&bits) => bits,
+        _ => {
+            println!(
+                "{} needs a bit count between 1 and 15, found '{}'",
+                name, raw
+            );
+            process::exit(1);
+        }
     }
 }
 
 fn main() {
+    let mut plane = DEFAULT_PLANE;
+    let mut show_map = false;
+    let mut to_encode = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rows" => plane.row_bits = parse_bits(&mut args, "--rows"),
+            "--cols" => plane.col_bits = parse_bits(&mut args, "--cols"),
+            "--map" => show_map = true,
+            "--encode" => to_encode.push(args.next().unwrap_or_default()),
+            _ => {
+                println!("Unknown argument '{}'", arg);
+                process::exit(1);
+            }
+        }
+    }
+
+    if !to_encode.is_empty() {
+        for raw in to_encode {
+            match raw.parse::<u32>() {
+                Ok(id) if id < plane.rows() * plane.cols() => {
+                    println!("{}: {}", id, plane.encode(plane.seat(id)))
+                }
+                _ => println!("'{}' is not a seat id of this plane", raw),
+            }
+        }
+        return;
+    }
+
     let raw = fs::read_to_string("input.txt").unwrap();
 
     let mut seats = vec![];
-
-    let mut max_id = 0;
-    for line in raw.split("\n") {
-        if line.len() > 0 {
-            let seat = Seat::from_string(line.to_string());
-            max_id = max_id.max(seat.id());
-            seats.push(seat);
+    for line in raw.lines().filter(|line| !line.is_empty()) {
+        match plane.decode(line) {
+            Ok(seat) => seats.push(seat),
+            Err(err) => println!("Skipping boarding pass: {}", err),
         }
     }
 
+    let max_id = seats.iter().map(|&s| plane.id(s)).max().unwrap_or(0);
     println!("The max seat id is: {}", max_id);
 
-    seats.sort_by(|a, b| a.id().cmp(&b.id()));
-    for i in 0..(seats.len() - 1) {
-        if seats[i].id() == seats[i + 1].id() - 2 {
-            println!("The target seat id is: {}", seats[i].id() + 1);
-            break;
-        }
+    let missing = plane.missing(&seats);
+    if let Some(id) = missing.gaps.first() {
+        println!("The target seat id is: {}", id);
+    }
+    if missing.gaps.len() > 1 {
+        println!("All missing seat ids in between: {:?}", missing.gaps);
+    }
+    println!(
+        "Free seats at the front: {:?}, at the back: {:?}",
+        missing.front, missing.back
+    );
+
+    if show_map {
+        println!("{}", plane.render(&seats));
     }
 }
 
 fn decode(raw: String, upper: char) -> i32 {
     let mut sum = 0;
     let rev: Vec<char> = raw.chars().rev().collect();
-    for i in 0..raw.len() {
-        if rev[i] == upper {
+    for (i, &c) in rev.iter().enumerate() {
+        if c == upper {
             sum += 2i32.pow(i as u32);
         }
     }
-    return sum;
+    sum
+}
+
+fn encode(value: u32, bits: u32, lower: char, upper: char) -> String {
+    (0..bits)
+        .rev()
+        .map(|i| if value >> i & 1 == 1 { upper } else { lower })
+        .collect()
 }
 
 #[cfg(test)]
@@ -67,8 +218,73 @@ mod tests {
 
     #[test]
     fn decode_complex_examples() {
-        assert_eq!(Seat::from_string("BFFFBBFRRR".to_string()).id(), 567);
-        assert_eq!(Seat::from_string("FFFBBBFRRR".to_string()).id(), 119);
-        assert_eq!(Seat::from_string("BBFFBBFRLL".to_string()).id(), 820);
+        let id = |raw| DEFAULT_PLANE.id(DEFAULT_PLANE.decode(raw).unwrap());
+        assert_eq!(id("BFFFBBFRRR"), 567);
+        assert_eq!(id("FFFBBBFRRR"), 119);
+        assert_eq!(id("BBFFBBFRLL"), 820);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        assert_eq!(DEFAULT_PLANE.encode(DEFAULT_PLANE.seat(567)), "BFFFBBFRRR");
+        let plane = Plane {
+            row_bits: 4,
+            col_bits: 2,
+        };
+        for id in 0..plane.rows() * plane.cols() {
+            let pass = plane.encode(plane.seat(id));
+            assert_eq!(pass.len(), 6);
+            assert_eq!(plane.id(plane.decode(&pass).unwrap()), id);
+        }
+        assert!(plane.decode("BFFFBBFRRR").is_err());
+        assert!(plane.decode("BFXFRR").is_err());
+        // the length is counted in characters, not bytes
+        assert_eq!(
+            DEFAULT_PLANE.decode("FBFBBFéRL"),
+            Err("'FBFBBFéRL' should be 10 characters long".to_string())
+        );
+        assert_eq!(
+            DEFAULT_PLANE.decode("FBFBBFéRLR"),
+            Err("'FBFBBFéRLR' has 'é' in the row part".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_and_render() {
+        let plane = Plane {
+            row_bits: 2,
+            col_bits: 2,
+        };
+        let seats: Vec<Seat> = [2, 3, 5, 8, 9].iter().map(|&id| plane.seat(id)).collect();
+        assert_eq!(
+            plane.missing(&seats),
+            Missing {
+                front: 0..2,
+                gaps: vec![4, 6, 7],
+                back: 10..16,
+            }
+        );
+        assert_eq!(
+            plane.missing(&[]),
+            Missing {
+                front: 0..16,
+                gaps: vec![],
+                back: 16..16,
+            }
+        );
+        assert_eq!(plane.render(&seats), "0 ..##\n1 .#..\n2 ##..\n3 ....");
+    }
+
+    #[test]
+    fn missing_on_a_large_plane() {
+        let plane = Plane {
+            row_bits: 15,
+            col_bits: 15,
+        };
+        let seats: Vec<Seat> = [5, 7, 7, 10].iter().map(|&id| plane.seat(id)).collect();
+        let missing = plane.missing(&seats);
+        assert_eq!(missing.front, 0..5);
+        assert_eq!(missing.gaps, vec![6, 8, 9]);
+        assert_eq!(missing.back, 11..1 << 30);
     }
 }
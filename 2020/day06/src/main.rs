@@ -1,46 +1,182 @@
-use std::collections::HashSet;
-use std::fs;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
 
-fn answer_union(group: String) -> HashSet<char> {
-    let mut set = HashSet::new();
-    for line in group.split("\n") {
-        set.extend(&HashSet::<char>::from_iter(line.chars()));
+/// The questions a person answered "yes" to, bit i set for letter 'a' + i.
+type Answers = u32;
+
+fn answers(line: &str) -> Result<Answers, String> {
+    line.chars().try_fold(0, |acc, c| match c {
+        'a'..='z' => Ok(acc | 1 << (c as u32 - 'a' as u32)),
+        _ => Err(format!("'{}' is not a question", c)),
+    })
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    members: Vec<Answers>,
+}
+
+impl Group {
+    /// How many members answered each question.
+    fn tally(&self) -> [usize; 26] {
+        let mut tally = [0; 26];
+        for member in self.members.iter() {
+            for (i, count) in tally.iter_mut().enumerate() {
+                *count += (member >> i & 1) as usize;
+            }
+        }
+        tally
+    }
+
+    /// The questions answered by at least `k` members of the group.
+    fn at_least(&self, k: usize) -> Answers {
+        self.tally()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0 && count >= k)
+            .fold(0, |acc, (i, _)| acc | 1 << i)
+    }
+
+    fn union(&self) -> Answers {
+        self.at_least(1)
+    }
+
+    fn inters(&self) -> Answers {
+        self.at_least(self.members.len())
     }
-    set
 }
 
-fn answer_inters(group: String) -> HashSet<char> {
-    let mut set = HashSet::new();
-    for line in group.split("\n") {
-        let subset = HashSet::<char>::from_iter(line.chars());
-        if set.len() == 0 {
-            set.extend(&subset);
-        } else {
-            set = set.into_iter().filter(|e| subset.contains(e)).collect();
-            if set.len() == 0 {
-                break;
+/// Reads the groups one at a time, so that only the current group is ever
+/// kept in memory.
+struct Groups<R: BufRead> {
+    lines: io::Lines<R>,
+    line: usize,
+}
+
+impl<R: BufRead> Groups<R> {
+    fn new(reader: R) -> Self {
+        Groups {
+            lines: reader.lines(),
+            line: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Groups<R> {
+    type Item = Result<Group, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut group = Group::default();
+        for line in self.lines.by_ref() {
+            self.line += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(format!("line {}: {}", self.line, err))),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                if group.members.is_empty() {
+                    continue;
+                }
+                return Some(Ok(group));
+            }
+            match answers(line) {
+                Ok(member) => group.members.push(member),
+                Err(err) => return Some(Err(format!("line {}: {}", self.line, err))),
             }
         }
+        if group.members.is_empty() {
+            None
+        } else {
+            Some(Ok(group))
+        }
     }
-    set
+}
+
+fn letters(answers: Answers) -> String {
+    ('a'..='z')
+        .enumerate()
+        .filter(|&(i, _)| answers >> i & 1 == 1)
+        .map(|(_, c)| c)
+        .collect()
 }
 
 fn main() {
-    let file = fs::read_to_string("input.txt").unwrap();
+    let mut path = "input.txt".to_string();
+    let mut quorum = None;
+    let mut stats = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stats" => stats = true,
+            "--at-least" => {
+                quorum = match args.next().map(|k| k.parse::<usize>()) {
+                    Some(Ok(k)) => Some(k),
+                    _ => {
+                        println!("--at-least needs a number of members");
+                        process::exit(1);
+                    }
+                }
+            }
+            _ => path = arg,
+        }
+    }
+
+    let file = File::open(&path).unwrap_or_else(|err| {
+        println!("Failed to open '{}'. {}", path, err);
+        process::exit(1);
+    });
+
     let mut sum_union = 0;
     let mut sum_inters = 0;
-    for group in file.split("\n\n") {
-        sum_union += answer_union(group.to_string()).len();
-        sum_inters += answer_inters(group.to_string()).len();
+    let mut sum_quorum = 0;
+    for (i, group) in Groups::new(BufReader::new(file)).enumerate() {
+        let group = group.unwrap_or_else(|err| {
+            println!("Failed to read '{}'. {}", path, err);
+            process::exit(1);
+        });
+        let (union, inters) = (group.union(), group.inters());
+        sum_union += union.count_ones();
+        sum_inters += inters.count_ones();
+        if let Some(k) = quorum {
+            sum_quorum += group.at_least(k).count_ones();
+        }
+        if stats {
+            println!(
+                "group {}: {} members, {} answered by anyone ({}), {} by everyone ({})",
+                i + 1,
+                group.members.len(),
+                union.count_ones(),
+                letters(union),
+                inters.count_ones(),
+                letters(inters),
+            );
+        }
     }
     println!("The sum of the groups questions is: {}", sum_union);
     println!("The sum of the groups consensus is: {}", sum_inters);
+    if let Some(k) = quorum {
+        println!(
+            "The sum of the questions answered by at least {}: {}",
+            k, sum_quorum
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use crate::*;
+    use std::collections::HashSet;
+    use std::fs;
+
+    fn groups(raw: &str) -> Vec<Group> {
+        Groups::new(raw.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
 
     #[test]
     fn set_test() {
@@ -54,8 +190,8 @@ mod tests {
     fn simple_union() {
         let file = fs::read_to_string("example.txt").unwrap();
         let mut sum = 0;
-        for group in file.split("\n\n") {
-            sum += answer_union(group.to_string()).len();
+        for group in groups(&file) {
+            sum += group.union().count_ones();
         }
         assert_eq!(sum, 11);
     }
@@ -64,9 +200,21 @@ mod tests {
     fn simple_inters() {
         let file = fs::read_to_string("example.txt").unwrap();
         let mut sum = 0;
-        for group in file.split("\n\n") {
-            sum += answer_inters(group.to_string()).len();
+        for group in groups(&file) {
+            sum += group.inters().count_ones();
         }
         assert_eq!(sum, 6);
     }
+
+    #[test]
+    fn quorum_and_stream() {
+        let file = File::open("example.txt").unwrap();
+        assert_eq!(Groups::new(BufReader::new(file)).count(), 5);
+        let group = &groups("abc\nab\nad")[0];
+        assert_eq!(letters(group.at_least(1)), "abcd");
+        assert_eq!(letters(group.at_least(2)), "ab");
+        assert_eq!(letters(group.at_least(3)), "a");
+        assert_eq!(group.at_least(4), 0);
+        assert!(Groups::new("ab\n\naB".as_bytes()).nth(1).unwrap().is_err());
+    }
 }
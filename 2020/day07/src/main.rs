@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fmt;
 use std::fs;
 use std::num::ParseIntError;
 use std::str::FromStr;
@@ -54,92 +56,176 @@ impl FromStr for Rule {
     }
 }
 
-fn solve(raw: String) -> (i32, u64) {
-    let mut rules: Vec<Rule> = vec![];
-    for line in raw.split("\n") {
-        if line.len() > 0 {
-            let rule = Rule::from_str(line.trim_end_matches('.')).unwrap();
-            rules.push(rule);
+#[derive(Debug, PartialEq)]
+enum ContentsError {
+    /// The bags on a cycle, the first bag repeated at the end.
+    Cycle(Vec<String>),
+    /// The bag whose count does not fit in a `u64`.
+    Overflow(String),
+}
+
+impl fmt::Display for ContentsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContentsError::Cycle(cycle) => {
+                write!(f, "the rules are cyclic: {}", cycle.join(" -> "))
+            }
+            ContentsError::Overflow(bag) => {
+                write!(f, "the number of bags in a {} bag overflows", bag)
+            }
         }
     }
+}
 
-    let shinygolds: Vec<&Rule> = rules
-        .iter()
-        .filter(|r| {
-            r.contents.contains_key(&Bag {
-                name: "shiny gold".to_string(),
-            })
-        })
-        .collect();
+/// The bag rules as a weighted graph, an edge from a bag to each bag it
+/// directly contains.
+#[derive(Debug, Default)]
+struct BagGraph {
+    contents: BTreeMap<String, Vec<(String, u64)>>,
+    containers: BTreeMap<String, Vec<String>>,
+}
 
-    let mut frontier = HashMap::<Bag, i32>::new();
-    for shinygold in shinygolds.clone() {
-        frontier.insert(shinygold.bag.clone(), 1);
+impl BagGraph {
+    fn from_rules(rules: &[Rule]) -> Self {
+        let mut graph = BagGraph::default();
+        for rule in rules {
+            let mut contents: Vec<(String, u64)> = rule
+                .contents
+                .iter()
+                .map(|(bag, &num)| (bag.name.clone(), num as u64))
+                .collect();
+            contents.sort();
+            for (inner, _) in contents.iter() {
+                graph
+                    .containers
+                    .entry(inner.clone())
+                    .or_default()
+                    .push(rule.bag.name.clone());
+            }
+            graph
+                .contents
+                .entry(rule.bag.name.clone())
+                .or_default()
+                .extend(contents);
+        }
+        graph
     }
 
-    loop {
-        let mut explored = Vec::<&Rule>::new();
-        for (bag, _) in frontier.iter() {
-            for rule in rules.iter().filter(|r| r.contents.contains_key(bag)) {
-                if !explored.contains(&&rule) && !frontier.contains_key(&rule.bag) {
-                    explored.push(&rule);
-                }
+    fn reach(&self, start: &str, next: impl Fn(&str) -> Vec<String>) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut frontier = next(start);
+        while let Some(bag) = frontier.pop() {
+            if !seen.contains(&bag) {
+                frontier.extend(next(&bag));
+                seen.insert(bag);
             }
         }
+        seen
+    }
 
-        if explored.is_empty() {
-            break;
-        }
+    /// Every bag that can eventually hold a `colour` bag.
+    fn ancestors(&self, colour: &str) -> HashSet<String> {
+        self.reach(colour, |bag| {
+            self.containers.get(bag).cloned().unwrap_or_default()
+        })
+    }
 
-        for rule in explored {
-            frontier.insert(rule.bag.clone(), 1);
-        }
+    /// Every bag that can end up inside a `colour` bag.
+    fn descendants(&self, colour: &str) -> HashSet<String> {
+        self.reach(colour, |bag| {
+            self.contents.get(bag).map_or(vec![], |inner| {
+                inner.iter().map(|(b, _)| b.clone()).collect()
+            })
+        })
     }
 
-    let mut leaves = 0u64;
-    let mut expand = Vec::<(Bag, i32)>::new();
-    expand.push((
-        Bag {
-            name: "shiny gold".to_string(),
-        },
-        1,
-    ));
-
-    loop {
-        let mut added = Vec::<(Bag, i32)>::new();
-        if let Some((bag, num)) = expand.pop() {
-            if !(bag.name == "shiny gold") {
-                leaves += num as u64;
-            }
-            if let Some(rule) = rules.iter().find(|r| r.bag == bag) {
-                let mut contents = rule.contents.iter().collect::<Vec<(&Bag, &i32)>>();
-                contents.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    /// The number of bags inside a `colour` bag, counting every level of
+    /// nesting. Each bag is only expanded once.
+    fn total_contents(&self, colour: &str) -> Result<u64, ContentsError> {
+        let mut memo = HashMap::new();
+        let mut path = vec![];
+        self.count(colour, &mut memo, &mut path)
+    }
 
-                for (inbag, innum) in contents {
-                    added.push((inbag.clone().clone(), *innum * num));
-                }
-            }
-        } else {
-            break;
+    fn count<'a>(
+        &'a self,
+        bag: &'a str,
+        memo: &mut HashMap<&'a str, u64>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<u64, ContentsError> {
+        if let Some(&total) = memo.get(bag) {
+            return Ok(total);
+        }
+        if let Some(start) = path.iter().position(|&b| b == bag) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|b| b.to_string()).collect();
+            cycle.push(bag.to_string());
+            return Err(ContentsError::Cycle(cycle));
         }
-        for (bag, num) in added.iter() {
-            expand.push((bag.clone(), *num));
+        path.push(bag);
+        let mut total: u64 = 0;
+        for (inner, num) in self.contents.get(bag).into_iter().flatten() {
+            total = self
+                .count(inner, memo, path)?
+                .checked_add(1)
+                .and_then(|bags| bags.checked_mul(*num))
+                .and_then(|bags| bags.checked_add(total))
+                .ok_or_else(|| ContentsError::Overflow(bag.to_string()))?;
         }
+        path.pop();
+        memo.insert(bag, total);
+        Ok(total)
     }
 
-    (frontier.len() as i32, leaves)
+    /// Renders the graph in the Graphviz DOT format, with the bag counts as
+    /// edge labels.
+    fn to_dot(&self) -> String {
+        let mut dot = "digraph bags {\n".to_string();
+        for (bag, contents) in self.contents.iter() {
+            dot += &format!("    \"{}\";\n", bag);
+            for (inner, num) in contents {
+                dot += &format!("    \"{}\" -> \"{}\" [label={}];\n", bag, inner, num);
+            }
+        }
+        dot + "}\n"
+    }
+}
+
+fn parse(raw: &str) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = vec![];
+    for line in raw.split('\n') {
+        if !line.is_empty() {
+            let rule = Rule::from_str(line.trim_end_matches('.')).unwrap();
+            rules.push(rule);
+        }
+    }
+    rules
 }
 
 fn main() {
     let raw = fs::read_to_string("input.txt").unwrap();
-    let (holders, leaves) = solve(raw);
+    let graph = BagGraph::from_rules(&parse(&raw));
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--dot") {
+        print!("{}", graph.to_dot());
+        return;
+    }
+    let colour = args.first().map_or("shiny gold", |c| c.as_str());
 
     println!(
-        "Bags that can hold at least one shiny gold bag: {}",
-        holders
+        "Bags that can hold at least one {} bag: {}",
+        colour,
+        graph.ancestors(colour).len()
     );
-
-    println!("Bags that needs to be held in a shiny gold bag: {}", leaves);
+    println!(
+        "Kinds of bags that can end up in a {} bag: {}",
+        colour,
+        graph.descendants(colour).len()
+    );
+    match graph.total_contents(colour) {
+        Ok(leaves) => println!("Bags that needs to be held in a {} bag: {}", colour, leaves),
+        Err(err) => println!("Cannot count the bags in a {} bag, {}", colour, err),
+    }
 }
 
 #[cfg(test)]
@@ -147,6 +233,13 @@ mod test {
 
     use crate::*;
 
+    fn solve(raw: String) -> (i32, u64) {
+        let graph = BagGraph::from_rules(&parse(&raw));
+        let holders = graph.ancestors("shiny gold").len() as i32;
+        let leaves = graph.total_contents("shiny gold").unwrap();
+        (holders, leaves)
+    }
+
     #[test]
     fn example_first() {
         let raw = fs::read_to_string("example.txt").unwrap();
@@ -162,4 +255,37 @@ mod test {
         assert_eq!(holders, 0);
         assert_eq!(leaves, 126);
     }
+
+    #[test]
+    fn queries_and_cycles() {
+        let raw = fs::read_to_string("example.txt").unwrap();
+        let graph = BagGraph::from_rules(&parse(&raw));
+        assert_eq!(graph.ancestors("muted yellow").len(), 2);
+        assert_eq!(graph.descendants("shiny gold").len(), 4);
+        assert_eq!(graph.total_contents("dark olive"), Ok(7));
+        assert!(graph
+            .to_dot()
+            .contains("\"muted yellow\" -> \"shiny gold\" [label=2];"));
+
+        let raw = "red bags contain 1 blue bag.\n\
+                   blue bags contain 2 green bags.\n\
+                   green bags contain 1 red bag.";
+        let graph = BagGraph::from_rules(&parse(raw));
+        let err = graph.total_contents("red").unwrap_err();
+        let cycle = ["red", "blue", "green", "red"].map(String::from).to_vec();
+        assert_eq!(err, ContentsError::Cycle(cycle));
+    }
+
+    #[test]
+    fn overflowing_contents() {
+        let raw: Vec<String> = (0..8)
+            .map(|i| format!("bag{} red bags contain 1000000 bag{} red bags.", i, i + 1))
+            .collect();
+        let graph = BagGraph::from_rules(&parse(&raw.join("\n")));
+        assert_eq!(graph.total_contents("bag5 red"), Ok(1000001000001000000));
+        assert_eq!(
+            graph.total_contents("bag0 red"),
+            Err(ContentsError::Overflow("bag4 red".to_string()))
+        );
+    }
 }
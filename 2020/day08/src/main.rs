@@ -1,8 +1,11 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fmt;
 use std::fs;
-use std::num::ParseIntError;
+use std::process;
 use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 enum OpCode {
     Nop,
     Acc,
@@ -10,49 +13,74 @@ enum OpCode {
 }
 
 impl FromStr for OpCode {
-    type Err = ParseIntError;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "acc" => Ok(Self::Acc),
             "nop" => Ok(Self::Nop),
             "jmp" => Ok(Self::Jmp),
-            _ => unreachable!(),
+            _ => Err(format!("unknown opcode '{}'", s)),
         }
     }
 }
 
-#[derive(Clone)]
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpCode::Nop => write!(f, "nop"),
+            OpCode::Acc => write!(f, "acc"),
+            OpCode::Jmp => write!(f, "jmp"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct Operation {
     opcode: OpCode,
     arg: i32,
 }
 
 impl FromStr for Operation {
-    type Err = ParseIntError;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (opcode, arg) = s.split_once(" ").unwrap();
+        let (opcode, arg) = s
+            .trim()
+            .split_once(' ')
+            .ok_or(format!("expected '<opcode> <arg>', found '{}'", s.trim()))?;
         Ok(Operation {
-            opcode: opcode.trim().parse::<OpCode>().unwrap(),
-            arg: arg.trim().parse().unwrap(),
+            opcode: opcode.trim().parse::<OpCode>()?,
+            arg: arg
+                .trim()
+                .parse()
+                .map_err(|err| format!("invalid argument '{}': {}", arg.trim(), err))?,
         })
     }
 }
 
-#[derive(Default, Clone)]
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+}", self.opcode, self.arg)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
 struct Program {
     ops: Vec<Operation>,
 }
 
 impl FromStr for Program {
-    type Err = ParseIntError;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut program = Program::default();
-        for line in s.split("\n") {
-            if !line.is_empty() {
-                let op = line.trim().parse::<Operation>().unwrap();
+        for (i, line) in s.split('\n').enumerate() {
+            if !line.trim().is_empty() {
+                let op = line
+                    .trim()
+                    .parse::<Operation>()
+                    .map_err(|err| format!("line {}: {}", i + 1, err))?;
                 program.ops.push(op);
             }
         }
@@ -60,66 +88,399 @@ impl FromStr for Program {
     }
 }
 
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for op in self.ops.iter() {
+            writeln!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assembles a program where `jmp` and `nop` may take a label instead of
+/// an offset. A label is declared with `name:`, either on its own line or
+/// before an instruction, and stands for the index of the next instruction.
+/// Everything after a `#` is a comment.
+fn assemble(src: &str) -> Result<Program, String> {
+    let mut labels = HashMap::new();
+    let mut lines = vec![];
+    for (i, line) in src.split('\n').enumerate() {
+        let mut line = line.split('#').next().unwrap().trim();
+        if let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(format!("line {}: invalid label '{}'", i + 1, label));
+            }
+            if labels.insert(label.to_string(), lines.len()).is_some() {
+                return Err(format!("line {}: label '{}' declared twice", i + 1, label));
+            }
+            line = rest.trim();
+        }
+        if !line.is_empty() {
+            lines.push((i + 1, line));
+        }
+    }
+
+    let mut program = Program::default();
+    for (index, &(number, line)) in lines.iter().enumerate() {
+        let (opcode, arg) = line
+            .split_once(' ')
+            .ok_or(format!("line {}: expected '<opcode> <arg>'", number))?;
+        let opcode: OpCode = opcode
+            .parse()
+            .map_err(|err| format!("line {}: {}", number, err))?;
+        let arg = arg.trim();
+        let arg = match arg.parse::<i32>() {
+            Ok(arg) => arg,
+            Err(_) if opcode == OpCode::Acc && labels.contains_key(arg) => {
+                return Err(format!(
+                    "line {}: label '{}' used with acc, labels are only valid on jmp and nop",
+                    number, arg
+                ))
+            }
+            Err(err) if opcode == OpCode::Acc => {
+                return Err(format!(
+                    "line {}: '{}' is not a number: {}",
+                    number, arg, err
+                ))
+            }
+            Err(_) if labels.contains_key(arg) => labels[arg] as i32 - index as i32,
+            Err(_) => return Err(format!("line {}: unknown label '{}'", number, arg)),
+        };
+        program.ops.push(Operation { opcode, arg });
+    }
+    Ok(program)
+}
+
+/// Why the processor stopped.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Exit {
+    /// The cursor moved right past the last instruction.
+    Halted,
+    /// The instruction at `at` was about to run a second time.
+    Looped { at: usize },
+    /// The cursor jumped to `at`, which is neither an instruction nor the
+    /// end of the program.
+    OutOfBounds { at: i32 },
+    /// The cursor reached the breakpoint at `at`, which has not run yet.
+    Breakpoint { at: usize },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Step {
+    cursor: usize,
+    acc: i32,
+}
+
 #[derive(Clone)]
 struct Processor {
     program: Program,
     acc: i32,
     cursor: i32,
-    visited: Vec<i32>,
+    visited: Vec<bool>,
+    breakpoints: HashSet<usize>,
+    /// The breakpoint the last run stopped at, skipped when running again.
+    paused: Option<usize>,
+    trace: Option<Vec<Step>>,
 }
 
 impl Processor {
-    fn run_until_loop(&mut self) -> i32 {
+    fn new(program: Program) -> Self {
+        Processor {
+            visited: vec![false; program.ops.len()],
+            program,
+            acc: 0,
+            cursor: 0,
+            breakpoints: HashSet::new(),
+            paused: None,
+            trace: None,
+        }
+    }
+
+    /// Records the cursor and the accumulator before every instruction.
+    fn with_trace(mut self) -> Self {
+        self.trace = Some(vec![]);
+        self
+    }
+
+    fn set_breakpoint(&mut self, at: usize) {
+        self.breakpoints.insert(at);
+    }
+
+    /// Where the cursor currently stands, if it is not on an instruction.
+    fn exit(&self) -> Option<Exit> {
+        let len = self.program.ops.len() as i32;
+        if self.cursor == len {
+            Some(Exit::Halted)
+        } else if !(0..len).contains(&self.cursor) {
+            Some(Exit::OutOfBounds { at: self.cursor })
+        } else if self.visited[self.cursor as usize] {
+            Some(Exit::Looped {
+                at: self.cursor as usize,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Runs the instruction under the cursor, unless the program is over.
+    fn step(&mut self) -> Option<Exit> {
+        if let Some(exit) = self.exit() {
+            return Some(exit);
+        }
+        let cursor = self.cursor as usize;
+        self.paused = None;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(Step {
+                cursor,
+                acc: self.acc,
+            });
+        }
+        self.visited[cursor] = true;
+        let op = &self.program.ops[cursor];
+        match op.opcode {
+            OpCode::Acc => {
+                self.cursor += 1;
+                self.acc += op.arg
+            }
+            OpCode::Jmp => self.cursor += op.arg,
+            OpCode::Nop => self.cursor += 1,
+        }
+        None
+    }
+
+    /// Runs until the program is over or the cursor lands on a breakpoint.
+    /// Running again after a breakpoint resumes from it.
+    fn run(&mut self) -> Exit {
+        let mut resumed = self.paused.take();
         loop {
-            let current_instruction = &self.program.ops[self.cursor as usize];
-            if self.visited.contains(&self.cursor) {
-                break;
+            if self.exit().is_none() {
+                let at = self.cursor as usize;
+                if resumed.take() != Some(at) && self.breakpoints.contains(&at) {
+                    self.paused = Some(at);
+                    return Exit::Breakpoint { at };
+                }
+            }
+            if let Some(exit) = self.step() {
+                return exit;
+            }
+        }
+    }
+}
+
+impl Program {
+    /// Where each instruction sends the cursor, once flipped if `flip`.
+    fn target(&self, i: usize, flip: bool) -> i32 {
+        let op = &self.ops[i];
+        match (&op.opcode, flip) {
+            (OpCode::Jmp, false) | (OpCode::Nop, true) => i as i32 + op.arg,
+            _ => i as i32 + 1,
+        }
+    }
+
+    /// Finds the instruction to swap between `jmp` and `nop` so that the
+    /// program halts.
+    ///
+    /// Walking the jumps backwards from the end marks every instruction that
+    /// already halts, then the first instruction of the original run whose
+    /// flipped target is one of them is the fix. Both passes are linear.
+    fn repair(&self) -> Option<usize> {
+        let len = self.ops.len();
+        let mut sources = vec![vec![]; len + 1];
+        for i in 0..len {
+            let target = self.target(i, false);
+            if (0..=len as i32).contains(&target) {
+                sources[target as usize].push(i);
             }
-            self.visited.push(self.cursor);
-            match current_instruction.opcode {
-                OpCode::Acc => {
-                    self.cursor += 1;
-                    self.acc += current_instruction.arg
+        }
+        let mut halts = vec![false; len + 1];
+        let mut frontier = vec![len];
+        while let Some(i) = frontier.pop() {
+            if !halts[i] {
+                halts[i] = true;
+                frontier.extend(sources[i].iter());
+            }
+        }
+
+        let mut cursor = 0;
+        let mut visited = vec![false; len];
+        while cursor < len && !visited[cursor] {
+            visited[cursor] = true;
+            if !matches!(self.ops[cursor].opcode, OpCode::Acc) {
+                let target = self.target(cursor, true);
+                if (0..=len as i32).contains(&target) && halts[target as usize] {
+                    return Some(cursor);
                 }
-                OpCode::Jmp => self.cursor += current_instruction.arg,
-                OpCode::Nop => self.cursor += 1,
             }
-            if !(0..(self.program.ops.len() as i32 - 1)).contains(&self.cursor) {
+            let target = self.target(cursor, false);
+            if !(0..len as i32).contains(&target) {
                 break;
             }
+            cursor = target as usize;
+        }
+        None
+    }
+
+    fn flip(&mut self, i: usize) {
+        self.ops[i].opcode = match self.ops[i].opcode {
+            OpCode::Nop => OpCode::Jmp,
+            OpCode::Jmp => OpCode::Nop,
+            OpCode::Acc => OpCode::Acc,
         }
-        self.acc
+    }
+}
+
+/// A run of instructions that is only entered at its first instruction and
+/// only branches at its last one.
+#[derive(Debug, PartialEq)]
+struct Block {
+    start: usize,
+    end: usize,
+    /// Where the cursor goes after the last instruction.
+    next: i32,
+}
+
+impl Program {
+    fn basic_blocks(&self) -> Vec<Block> {
+        let len = self.ops.len();
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (i, op) in self.ops.iter().enumerate() {
+            if op.opcode == OpCode::Jmp {
+                let target = self.target(i, false);
+                if (0..len as i32).contains(&target) {
+                    leaders.insert(target as usize);
+                }
+                leaders.insert(i + 1);
+            }
+        }
+        let leaders: Vec<usize> = leaders.into_iter().filter(|&i| i < len).collect();
+        leaders
+            .iter()
+            .enumerate()
+            .map(|(n, &start)| {
+                let end = leaders.get(n + 1).copied().unwrap_or(len);
+                Block {
+                    start,
+                    end,
+                    next: self.target(end - 1, false),
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the control-flow graph between basic blocks in the Graphviz
+    /// DOT format, with the end of the program as a `halt` node.
+    fn to_dot(&self) -> String {
+        let len = self.ops.len() as i32;
+        let mut dot = "digraph program {\n    node [shape=box, fontname=monospace];\n".to_string();
+        for block in self.basic_blocks() {
+            let label: String = (block.start..block.end)
+                .map(|i| format!("{}: {}\\l", i, self.ops[i]))
+                .collect();
+            dot += &format!("    b{} [label=\"{}\"];\n", block.start, label);
+            let target = block.next;
+            if target == len {
+                dot += &format!("    b{} -> halt;\n", block.start);
+            } else if (0..len).contains(&target) {
+                dot += &format!("    b{} -> b{};\n", block.start, target);
+            } else {
+                dot += &format!(
+                    "    b{} -> oob{};\n    oob{} [label=\"out of bounds ({})\", shape=plaintext];\n",
+                    block.start, target, target, target
+                );
+            }
+        }
+        dot + "    halt [shape=doublecircle];\n}\n"
     }
 }
 
 fn main() {
-    let processor = Processor {
-        program: fs::read_to_string("input.txt").unwrap().parse().unwrap(),
-        acc: 0,
-        cursor: 0,
-        visited: vec![],
+    let mut args = env::args().skip(1);
+    let mut breakpoints = vec![];
+    let mut trace = false;
+    let mut source = None;
+    let mut disassemble = false;
+    let mut cfg = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace = true,
+            "--disasm" => disassemble = true,
+            "--cfg" => cfg = true,
+            "--asm" => match args.next() {
+                Some(path) => source = Some(path),
+                None => {
+                    println!("--asm needs a file");
+                    process::exit(1);
+                }
+            },
+            "--break" => match args.next().map(|at| at.parse::<usize>()) {
+                Some(Ok(at)) => breakpoints.push(at),
+                _ => {
+                    println!("--break needs an instruction index");
+                    process::exit(1);
+                }
+            },
+            _ => {
+                println!("Unknown argument '{}'", arg);
+                process::exit(1);
+            }
+        }
+    }
+
+    let program = match source {
+        Some(path) => fs::read_to_string(&path)
+            .map_err(|err| format!("failed to open '{}'. {}", path, err))
+            .and_then(|src| assemble(&src)),
+        None => fs::read_to_string("input.txt").unwrap().parse(),
     };
-    println!(
-        "Value of the accumulator at loop detection: {}",
-        processor.clone().run_until_loop()
-    );
-
-    for i in 0..processor.program.ops.len() {
-        let mut attempt = processor.clone();
-        match attempt.program.ops[i as usize].opcode {
-            OpCode::Nop => attempt.program.ops[i as usize].opcode = OpCode::Jmp,
-            OpCode::Jmp => attempt.program.ops[i as usize].opcode = OpCode::Nop,
-            OpCode::Acc => (),
-        }
-        attempt.run_until_loop();
-        if attempt.cursor == attempt.program.ops.len() as i32 - 1 {
+    let program = program.unwrap_or_else(|err| {
+        println!("Failed to load the program: {}", err);
+        process::exit(1);
+    });
+    if disassemble {
+        print!("{}", program);
+        return;
+    }
+    if cfg {
+        print!("{}", program.to_dot());
+        return;
+    }
+
+    let mut processor = Processor::new(program.clone());
+    if trace {
+        processor = processor.with_trace();
+    }
+    for at in breakpoints {
+        processor.set_breakpoint(at);
+    }
+    loop {
+        let exit = processor.run();
+        if let Exit::Breakpoint { at } = exit {
+            println!("Breakpoint at {}, accumulator: {}", at, processor.acc);
+            continue;
+        }
+        println!("Value of the accumulator at {:?}: {}", exit, processor.acc);
+        break;
+    }
+    if let Some(trace) = processor.trace {
+        for step in trace {
+            println!("{:>5}  acc {}", step.cursor, step.acc);
+        }
+    }
+
+    match program.repair() {
+        Some(i) => {
+            let mut fixed = program.clone();
+            fixed.flip(i);
+            let mut processor = Processor::new(fixed);
+            let exit = processor.run();
             println!(
-                "Value of the accumulator of the fixed program (by changing {}): {}",
-                i,
-                attempt.clone().run_until_loop(),
+                "Value of the accumulator of the fixed program (by changing {}): {} ({:?})",
+                i, processor.acc, exit
             );
-            break;
         }
+        None => println!("No single instruction swap makes the program halt"),
     }
 }
 
@@ -140,25 +501,95 @@ mod test {
             acc +1
             jmp -4
             acc +6";
-        let processor = Processor {
-            program: input.parse::<Program>().unwrap(),
-            acc: 0,
-            cursor: 0,
-            visited: vec![],
-        };
-        assert_eq!(5, processor.clone().run_until_loop());
-        for i in 0..processor.program.ops.len() {
-            let mut attempt = processor.clone();
-            match attempt.program.ops[i as usize].opcode {
-                OpCode::Nop => attempt.program.ops[i as usize].opcode = OpCode::Jmp,
-                OpCode::Jmp => attempt.program.ops[i as usize].opcode = OpCode::Nop,
-                OpCode::Acc => (),
-            }
-            attempt.run_until_loop();
-            if attempt.cursor == attempt.program.ops.len() as i32 {
-                assert_eq!(8, attempt.clone().run_until_loop(),);
-                break;
-            }
-        }
+        let program = input.parse::<Program>().unwrap();
+        let mut processor = Processor::new(program.clone());
+        assert_eq!(processor.run(), Exit::Looped { at: 1 });
+        assert_eq!(5, processor.acc);
+
+        let i = program.repair().unwrap();
+        assert_eq!(i, 7);
+        let mut fixed = program.clone();
+        fixed.flip(i);
+        let mut attempt = Processor::new(fixed);
+        assert_eq!(attempt.run(), Exit::Halted);
+        assert_eq!(attempt.cursor, attempt.program.ops.len() as i32);
+        assert_eq!(8, attempt.acc);
+    }
+
+    #[test]
+    fn stepping_and_breakpoints() {
+        let program = "nop +0\nacc +2\njmp +2\nacc +9\nacc +1"
+            .parse::<Program>()
+            .unwrap();
+        let mut processor = Processor::new(program.clone()).with_trace();
+        processor.set_breakpoint(4);
+        assert_eq!(processor.step(), None);
+        assert_eq!(processor.cursor, 1);
+        assert_eq!(processor.run(), Exit::Breakpoint { at: 4 });
+        assert_eq!(processor.acc, 2);
+        assert_eq!(processor.run(), Exit::Halted);
+        assert_eq!(processor.acc, 3);
+        let visited: Vec<usize> = processor.trace.unwrap().iter().map(|s| s.cursor).collect();
+        assert_eq!(visited, vec![0, 1, 2, 4]);
+
+        // a breakpoint on the first instruction stops before it runs
+        let mut processor = Processor::new("nop +0\nacc +1\njmp -2".parse().unwrap());
+        processor.set_breakpoint(0);
+        assert_eq!(processor.run(), Exit::Breakpoint { at: 0 });
+        assert_eq!(processor.cursor, 0);
+        assert_eq!(processor.run(), Exit::Looped { at: 0 });
+        assert_eq!(processor.acc, 1);
+
+        let mut jumper = Processor::new("jmp -1".parse().unwrap());
+        assert_eq!(jumper.run(), Exit::OutOfBounds { at: -1 });
+        let stuck: Program = "jmp +2\njmp -1\njmp -1\njmp -3".parse().unwrap();
+        assert_eq!(stuck.repair(), None);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let raw = fs::read_to_string("input.txt").unwrap();
+        let program: Program = raw.parse().unwrap();
+        assert_eq!(program.to_string(), raw.trim_end().to_string() + "\n");
+        assert_eq!(program.to_string().parse::<Program>(), Ok(program));
+        assert!("nop +0\nmul +1".parse::<Program>().is_err());
+    }
+
+    #[test]
+    fn assembler_labels() {
+        let src = "
+            start: acc +1   # count
+            nop end
+            loop:
+            jmp start
+            end: acc -1";
+        let program = assemble(src).unwrap();
+        assert_eq!(program.to_string(), "acc +1\nnop +2\njmp -2\nacc -1\n");
+        assert!(assemble("jmp nowhere").is_err());
+        assert_eq!(
+            assemble("acc start\nstart: nop +0").unwrap_err(),
+            "line 1: label 'start' used with acc, labels are only valid on jmp and nop"
+        );
+        assert!(assemble("acc one")
+            .unwrap_err()
+            .starts_with("line 1: 'one' is not a number"));
+        assert!(assemble("a: nop +0\na: nop +0").is_err());
+    }
+
+    #[test]
+    fn control_flow_graph() {
+        let program = assemble("acc +1\nloop: jmp +2\nacc +3\nnop +0\njmp loop\njmp +5").unwrap();
+        let blocks: Vec<(usize, usize, i32)> = program
+            .basic_blocks()
+            .into_iter()
+            .map(|b| (b.start, b.end, b.next))
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![(0, 1, 1), (1, 2, 3), (2, 3, 3), (3, 5, 1), (5, 6, 10)]
+        );
+        let dot = program.to_dot();
+        assert!(dot.contains("b3 -> b1;"));
+        assert!(dot.contains("b5 -> oob10;"));
     }
 }
@@ -1,71 +1,143 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::fs;
+use std::ops::Range;
+use std::process;
 
 // sliding window with variable width
 // the next number in the sequence is the sum of two in the window
 // k[n] = k[i] + k[k] s.t. k-w < i,j < k, i != j, w = window width
 
-fn parse_seq(input: String) -> Vec<i64> {
-    let mut seq = Vec::new();
-    for line in input.split("\n") {
-        if let Ok(num) = line.trim().parse::<i64>() {
-            seq.push(num);
+/// Checks a stream of numbers against the sliding window of the `width`
+/// numbers before each of them.
+///
+/// The sums of every pair of distinct values in the window are kept in a
+/// multiset, so checking a number is a lookup and sliding the window only
+/// touches the pairs of the numbers that leave and enter it.
+struct Analyser {
+    width: usize,
+    window: VecDeque<i64>,
+    sums: HashMap<i64, usize>,
+}
+
+impl Analyser {
+    fn new(width: usize) -> Self {
+        Analyser {
+            width,
+            window: VecDeque::with_capacity(width + 1),
+            sums: HashMap::new(),
         }
     }
-    seq
-}
 
-fn check_in_window(window: &[i64], num: i64) -> bool {
-    for s in 0..window.len() {
-        for t in 0..window.len() {
-            if s != t && window[s] != window[t] && window[s] + window[t] == num {
-                return true;
+    /// Takes the next number, telling whether it is the sum of a pair in
+    /// the window. The numbers of the preamble are always valid.
+    fn push(&mut self, num: i64) -> bool {
+        let valid = self.window.len() < self.width || self.sums.contains_key(&num);
+        if self.window.len() == self.width {
+            if let Some(old) = self.window.pop_front() {
+                for &other in self.window.iter().filter(|&&other| other != old) {
+                    let count = self.sums.get_mut(&(old + other)).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        self.sums.remove(&(old + other));
+                    }
+                }
             }
         }
+        for &other in self.window.iter().filter(|&&other| other != num) {
+            *self.sums.entry(num + other).or_insert(0) += 1;
+        }
+        self.window.push_back(num);
+        valid
     }
-    false
 }
 
-fn find_weak(seq: &Vec<i64>, window_width: u32) -> Option<i64> {
-    for i in (window_width)..(seq.len() as u32) {
-        if !check_in_window(
-            &seq[(i - window_width) as usize..i as usize],
-            seq[i as usize],
-        ) {
-            return Some(seq[i as usize]);
+fn parse_seq(input: &str) -> Vec<i64> {
+    let mut seq = Vec::new();
+    for line in input.split("\n") {
+        if let Ok(num) = line.trim().parse::<i64>() {
+            seq.push(num);
         }
     }
-    None
+    seq
 }
 
-fn find_sequence(seq: &Vec<i64>, target: i64) -> Option<i64> {
-    let index = seq
-        .iter()
+/// Lists the index and value of every number that isn't the sum of a pair
+/// in its window.
+fn find_invalid(seq: &[i64], window_width: usize) -> Vec<(usize, i64)> {
+    let mut analyser = Analyser::new(window_width);
+    seq.iter()
         .enumerate()
-        .find(|(_, n)| **n == target)
-        .unwrap()
-        .0;
-    for amp in 2..index - 1 {
-        for i in 0..(index - amp) {
-            let range = &seq[i as usize..(i + amp) as usize];
-            let sum: i64 = range.iter().sum();
-            if sum == target {
-                let max = range.iter().max().unwrap();
-                let min = range.iter().min().unwrap();
-                return Some(max + min);
-            }
+        .filter(|&(_, &num)| !analyser.push(num))
+        .map(|(i, &num)| (i, num))
+        .collect()
+}
+
+/// Finds the first range of at least two contiguous numbers that sum to
+/// `target`, moving both ends of the range forward only. The numbers must
+/// not be negative.
+fn find_sequence(seq: &[i64], target: i64) -> Option<Range<usize>> {
+    let (mut lo, mut hi) = (0, 0);
+    let mut sum = 0;
+    loop {
+        if sum == target && hi - lo >= 2 {
+            return Some(lo..hi);
+        }
+        if (sum < target || hi - lo < 2) && hi < seq.len() {
+            sum += seq[hi];
+            hi += 1;
+        } else if lo < hi {
+            sum -= seq[lo];
+            lo += 1;
+        } else {
+            return None;
         }
     }
-    None
+}
+
+fn weakness(range: &[i64]) -> i64 {
+    range.iter().max().unwrap() + range.iter().min().unwrap()
 }
 
 fn main() {
-    let input = fs::read_to_string("input.txt").unwrap();
-    let seq = parse_seq(input.to_string());
-    let solved = find_weak(&seq, 25);
-    if let Some(num) = solved {
-        println!("Num which isn't a sum of a pair in the window: {}", num);
+    let mut width = 25;
+    let mut path = "input.txt".to_string();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => match args.next().map(|w| w.parse()) {
+                Some(Ok(w)) if w > 1 => width = w,
+                _ => {
+                    println!("--width needs a preamble of at least 2 numbers");
+                    process::exit(1);
+                }
+            },
+            _ => path = arg,
+        }
+    }
+
+    let raw = fs::read_to_string(&path).unwrap_or_else(|err| {
+        println!("Failed to open '{}'. {}", path, err);
+        process::exit(1);
+    });
+    let seq = parse_seq(&raw);
+    let invalid = find_invalid(&seq, width);
+    for &(index, num) in invalid.iter() {
+        println!(
+            "Num which isn't a sum of a pair in the window: {} (index {})",
+            num, index
+        );
+    }
+
+    if let Some(&(_, num)) = invalid.first() {
         if let Some(range) = find_sequence(&seq, num) {
-            println!("The sum of the lo..hi that sums to {}: {}", num, range);
+            println!(
+                "The sum of the lo..hi that sums to {} (indices {}..{}): {}",
+                num,
+                range.start,
+                range.end,
+                weakness(&seq[range.clone()])
+            );
         } else {
             println!("No number found");
         }
@@ -100,10 +172,35 @@ mod test {
             277
             309
             576";
-        let seq = parse_seq(input.to_string());
-        let solved = find_weak(&seq, 5);
+        let seq = parse_seq(input);
+        let solved = find_invalid(&seq, 5).first().map(|&(_, num)| num);
         assert_eq!(solved, Some(127));
         let range = find_sequence(&seq, 127);
-        assert_eq!(range, Some(62));
+        assert_eq!(range, Some(2..6));
+        assert_eq!(weakness(&seq[2..6]), 62);
+        assert_eq!(find_invalid(&seq, 5), vec![(14, 127)]);
+    }
+
+    #[test]
+    fn sliding_window() {
+        let seq: Vec<i64> = (0..400).map(|i| (i * 7919 % 61) as i64).collect();
+        for width in [2, 3, 5, 25] {
+            let brute: Vec<(usize, i64)> = (width..seq.len())
+                .filter(|&i| {
+                    let window = &seq[i - width..i];
+                    !window
+                        .iter()
+                        .any(|&a| window.iter().any(|&b| a != b && a + b == seq[i]))
+                })
+                .map(|i| (i, seq[i]))
+                .collect();
+            assert_eq!(find_invalid(&seq, width), brute);
+        }
+        let mut analyser = Analyser::new(2);
+        assert!(analyser.push(3) && analyser.push(3));
+        assert!(!analyser.push(6));
+        assert!(analyser.push(9));
+        assert_eq!(find_sequence(&[5, 1, 2, 7], 3), Some(1..3));
+        assert_eq!(find_sequence(&[5, 1, 2, 7], 5), None);
     }
 }
@@ -1,27 +1,95 @@
+use std::env;
 use std::fs;
+use std::process;
+
+/// The joltage differences allowed between two consecutive adapters.
+#[derive(Debug, Clone)]
+struct GapRule {
+    allowed: Vec<i32>,
+}
+
+impl GapRule {
+    fn max_gap(max: i32) -> Self {
+        GapRule {
+            allowed: (1..=max).collect(),
+        }
+    }
+
+    fn allows(&self, gap: i32) -> bool {
+        self.allowed.contains(&gap)
+    }
+
+    fn max(&self) -> i32 {
+        self.allowed.iter().copied().max().unwrap_or(0)
+    }
+}
 
 fn main() {
+    let mut rule = GapRule::max_gap(3);
+    let mut list = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => list = true,
+            "--gap" => match args.next().map(|max| max.parse()) {
+                Some(Ok(max)) if max > 0 => rule = GapRule::max_gap(max),
+                _ => {
+                    println!("--gap needs a positive maximum gap");
+                    process::exit(1);
+                }
+            },
+            "--allow" => {
+                let allowed: Option<Vec<i32>> = args.next().and_then(|gaps| {
+                    gaps.split(',')
+                        .map(|gap| gap.trim().parse().ok().filter(|&gap| gap > 0))
+                        .collect()
+                });
+                match allowed {
+                    Some(allowed) if !allowed.is_empty() => rule = GapRule { allowed },
+                    _ => {
+                        println!("--allow needs a comma separated list of positive gaps");
+                        process::exit(1);
+                    }
+                }
+            }
+            _ => {
+                println!("Unknown argument '{}'", arg);
+                process::exit(1);
+            }
+        }
+    }
+
     let raw = fs::read_to_string("input.txt").unwrap();
     let input: Vec<i32> = raw
-        .split("\n")
+        .split('\n')
         .filter_map(|s| s.parse::<i32>().ok())
         .collect();
     let res = solve(&input);
     println!("The number of 1 diff * 3 diff is: {}", res);
-    println!("The number of permutations is: {}", get_perm(&input));
+    println!(
+        "The number of permutations is: {}",
+        count_arrangements(&input, &rule)
+    );
+    if list {
+        for arrangement in Arrangements::new(&input, &rule) {
+            println!("{:?}", arrangement);
+        }
+    }
 }
 
-fn get_lohi(adas: &[i32]) -> Vec<i32> {
-    let mut lohi = adas.to_vec().clone();
+/// The outlet, the sorted adapters and the device, which is rated the
+/// largest allowed gap above the highest adapter.
+fn get_lohi(adas: &[i32], rule: &GapRule) -> Vec<i32> {
+    let mut lohi = adas.to_vec();
     lohi.sort();
-    lohi.push(lohi.last().unwrap() + 3);
+    lohi.push(lohi.last().unwrap_or(&0) + rule.max());
     let mut res = vec![0];
     res.append(&mut lohi);
     res
 }
 
 fn solve(adas: &[i32]) -> i32 {
-    let lohi = get_lohi(adas);
+    let lohi = get_lohi(adas, &GapRule::max_gap(3));
     let mut prev = 0;
     let mut count_1 = 0;
     let mut count_3 = 0;
@@ -36,48 +104,82 @@ fn solve(adas: &[i32]) -> i32 {
     count_1 * count_3
 }
 
-fn find_sub_perms(sub: Vec<i32>) -> i64 {
-    // bruteforce find permutation number
-    let mut perms = (sub.len() == 1) as i64;
-    let mut iter = sub.iter();
-    if let Some(first) = sub.first() {
-        for _ in 1..4 {
-            if let Some(next) = iter.next() {
-                //dbg!(next);
-                if next - first <= 3 {
-                    perms += find_sub_perms(iter.clone().map(|v| v.clone()).collect());
-                }
+/// Counts the ways to go from the first to the last element of a sorted
+/// chain, each step landing on a later element by an allowed gap.
+fn count_paths(chain: &[i32], rule: &GapRule) -> u128 {
+    if chain.is_empty() {
+        return 0;
+    }
+    let max = rule.max();
+    let mut ways = vec![0u128; chain.len()];
+    ways[0] = 1;
+    for i in 1..chain.len() {
+        for j in (0..i).rev() {
+            let gap = chain[i] - chain[j];
+            if gap > max {
+                break;
+            }
+            if rule.allows(gap) {
+                ways[i] += ways[j];
             }
         }
     }
-    perms
+    ways[chain.len() - 1]
 }
 
-fn get_perm(adas: &[i32]) -> i64 {
-    let lohi = get_lohi(adas);
-    let mut perms = 1;
-    let mut prev = 0;
-
-    // look ahead numbers up to distance 3
-    // see how many holes
-    // advance to highest
+fn count_arrangements(adas: &[i32], rule: &GapRule) -> u128 {
+    count_paths(&get_lohi(adas, rule), rule)
+}
 
-    let mut subs = vec![];
-    let mut sub = vec![];
+/// Lists the valid arrangements one at a time, as the adapters used from
+/// the outlet to the device. Meant for small inputs: there can be too many
+/// arrangements to go through.
+struct Arrangements {
+    chain: Vec<i32>,
+    rule: GapRule,
+    stack: Vec<Vec<usize>>,
+}
 
-    for ada in lohi {
-        if ada - prev == 3 {
-            subs.push(sub.clone());
-            sub.clear();
+impl Arrangements {
+    fn new(adas: &[i32], rule: &GapRule) -> Self {
+        Arrangements {
+            chain: get_lohi(adas, rule),
+            rule: rule.clone(),
+            stack: vec![vec![0]],
         }
-        sub.push(ada);
-        prev = ada
     }
+}
+
+impl Iterator for Arrangements {
+    type Item = Vec<i32>;
 
-    for sub in subs {
-        perms *= find_sub_perms(sub);
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.chain.len() - 1;
+        while let Some(path) = self.stack.pop() {
+            let last = *path.last().unwrap();
+            if last == end {
+                return Some(
+                    path[1..path.len() - 1]
+                        .iter()
+                        .map(|&i| self.chain[i])
+                        .collect(),
+                );
+            }
+            let reachable = (last + 1..=end)
+                .take_while(|&i| self.chain[i] - self.chain[last] <= self.rule.max())
+                .filter(|&i| self.rule.allows(self.chain[i] - self.chain[last]));
+            let mut next: Vec<Vec<usize>> = reachable
+                .map(|i| {
+                    let mut longer = path.clone();
+                    longer.push(i);
+                    longer
+                })
+                .collect();
+            next.reverse();
+            self.stack.extend(next);
+        }
+        None
     }
-    perms
 }
 
 #[cfg(test)]
@@ -90,6 +192,10 @@ mod test {
         17, 7, 9, 4, 2, 34, 10, 3,
     ];
 
+    fn get_perm(adas: &[i32]) -> u128 {
+        count_arrangements(adas, &GapRule::max_gap(3))
+    }
+
     #[test]
     fn simple_0() {
         assert_eq!(solve(&INPUT_0), 35);
@@ -112,6 +218,42 @@ mod test {
 
     #[test]
     fn perms() {
-        assert_eq!(find_sub_perms(vec![1, 2, 3, 4]), 4);
+        assert_eq!(count_paths(&[1, 2, 3, 4], &GapRule::max_gap(3)), 4);
+    }
+
+    #[test]
+    fn iterator_matches_counter() {
+        let rules = [
+            GapRule::max_gap(1),
+            GapRule::max_gap(3),
+            GapRule {
+                allowed: vec![1, 3],
+            },
+            GapRule {
+                allowed: vec![2, 5],
+            },
+        ];
+        for rule in rules.iter() {
+            for input in [&INPUT_0[..], &INPUT_1[..]] {
+                let listed = Arrangements::new(input, rule).count() as u128;
+                assert_eq!(listed, count_arrangements(input, rule));
+            }
+        }
+        let first = Arrangements::new(&INPUT_0, &GapRule::max_gap(3)).next();
+        assert_eq!(first, Some(vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19]));
+    }
+
+    #[test]
+    fn large_counts() {
+        let adas: Vec<i32> = (1..=120).collect();
+        let count = count_arrangements(&adas, &GapRule::max_gap(3));
+        // Every adapter is optional, so the count is the tribonacci number of
+        // the ways to climb from 0 to 120 in steps of 1, 2 or 3.
+        let mut ways: [u128; 3] = [1, 1, 2];
+        for _ in 3..=120 {
+            ways = [ways[1], ways[2], ways.iter().sum()];
+        }
+        assert_eq!(count, ways[2]);
+        assert!(count > u64::MAX as u128);
     }
 }
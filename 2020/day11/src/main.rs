@@ -1,6 +1,14 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
 use std::{fs, str::FromStr};
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 enum Tile {
     Empty,
     Occupied,
@@ -20,12 +28,12 @@ impl FromStr for Tile {
     }
 }
 
-impl ToString for Tile {
-    fn to_string(&self) -> String {
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Tile::Empty => "L".to_string(),
-            Tile::Floor => ".".to_string(),
-            Tile::Occupied => "#".to_string(),
+            Tile::Empty => write!(f, "L"),
+            Tile::Floor => write!(f, "."),
+            Tile::Occupied => write!(f, "#"),
         }
     }
 }
@@ -42,7 +50,7 @@ impl FromStr for Ferry {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut ferry = Ferry::default();
-        let lines = s.split("\n").filter(|l| l.len() > 0);
+        let lines = s.split('\n').filter(|l| !l.trim().is_empty());
         ferry.size_y = lines.clone().count() as u32;
         for line in lines {
             ferry.size_x = line.trim().len() as u32;
@@ -54,17 +62,19 @@ impl FromStr for Ferry {
     }
 }
 
-impl ToString for Ferry {
-    fn to_string(&self) -> String {
-        let mut res = String::default();
+impl fmt::Display for Ferry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for y in 0..self.size_y {
             for x in 0..self.size_x {
-                let i = x + y * self.size_x;
-                res += &self.layout[i as usize].to_string();
+                write!(
+                    f,
+                    "{}",
+                    self.layout[self.index(x as i32, y as i32).unwrap()]
+                )?;
             }
-            res += "\n";
+            writeln!(f)?;
         }
-        res
+        Ok(())
     }
 }
 
@@ -72,95 +82,353 @@ impl Ferry {
     fn occupied(&self) -> u32 {
         self.layout.iter().filter(|x| **x == Tile::Occupied).count() as u32
     }
-    fn star(&self, x: u32, y: u32, range: bool) -> Vec<&Tile> {
-        let mut star = vec![];
-        let mut spin = vec![];
-        for s in -1..2 as i32 {
-            for t in -1..2 as i32 {
-                if s != 0 || t != 0 {
-                    spin.push((s, t));
-                }
-            }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if (0..self.size_x as i32).contains(&x) && (0..self.size_y as i32).contains(&y) {
+            Some((x + y * self.size_x as i32) as usize)
+        } else {
+            None
         }
-        for (s, t) in spin {
-            if !range {
-                let xs = x as i32 + s;
-                let yt = y as i32 + t;
-                let i: usize = (xs + yt * self.size_x as i32) as usize;
-                if (0..self.size_x as i32).contains(&xs) && (0..self.size_y as i32).contains(&yt) {
-                    star.push(&self.layout[i]);
-                }
-            } else {
-                for d in 1..self.size_x as i32 {
-                    let xs = x as i32 + s * d;
-                    let yt = y as i32 + t * d;
-                    let i: usize = (xs + yt * self.size_x as i32) as usize;
-                    if (0..self.size_x as i32).contains(&xs)
-                        && (0..self.size_y as i32).contains(&yt)
-                        && self.layout[i] != Tile::Floor
-                    {
-                        star.push(&self.layout[i]);
-                        break;
-                    }
-                }
-            }
+    }
+}
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Chooses which tiles a tile looks at to decide its next state.
+trait Neighborhood {
+    /// The indices of the neighbours of the tile at `x`, `y`.
+    fn neighbours(&self, ferry: &Ferry, x: u32, y: u32) -> Vec<usize>;
+}
+
+/// The eight tiles around.
+struct Adjacent;
+
+/// The first seat seen in each of the eight directions, looking past the
+/// floor. Floor never changes, so the seats seen never change either.
+struct LineOfSight;
+
+/// The tiles at the given offsets.
+struct Offsets(Vec<(i32, i32)>);
+
+impl Neighborhood for Adjacent {
+    fn neighbours(&self, ferry: &Ferry, x: u32, y: u32) -> Vec<usize> {
+        Offsets(DIRECTIONS.to_vec()).neighbours(ferry, x, y)
+    }
+}
+
+impl Neighborhood for LineOfSight {
+    fn neighbours(&self, ferry: &Ferry, x: u32, y: u32) -> Vec<usize> {
+        DIRECTIONS
+            .iter()
+            .filter_map(|&(s, t)| {
+                (1..)
+                    .map(|d| ferry.index(x as i32 + s * d, y as i32 + t * d))
+                    .take_while(|i| i.is_some())
+                    .flatten()
+                    .find(|&i| ferry.layout[i] != Tile::Floor)
+            })
+            .collect()
+    }
+}
+
+impl Neighborhood for Offsets {
+    fn neighbours(&self, ferry: &Ferry, x: u32, y: u32) -> Vec<usize> {
+        self.0
+            .iter()
+            .filter_map(|&(s, t)| ferry.index(x as i32 + s, y as i32 + t))
+            .collect()
+    }
+}
+
+/// The next state of a tile given how many of its neighbours are occupied,
+/// the last entry standing for every larger count. An empty table leaves the
+/// tile as it is.
+#[derive(Debug, Clone)]
+struct RuleTable {
+    next: Vec<Tile>,
+}
+
+impl RuleTable {
+    fn next(&self, occupied: usize) -> Option<Tile> {
+        self.next.get(occupied).or(self.next.last()).copied()
+    }
+}
+
+/// A rule table for each tile state.
+#[derive(Debug, Clone)]
+struct Rules {
+    empty: RuleTable,
+    occupied: RuleTable,
+    floor: RuleTable,
+}
+
+impl Rules {
+    /// Empty seats with no occupied neighbour get taken, occupied seats
+    /// with at least `tolerance` occupied neighbours are left.
+    fn seating(tolerance: usize) -> Self {
+        let mut occupied = vec![Tile::Occupied; tolerance];
+        occupied.push(Tile::Empty);
+        Rules {
+            empty: RuleTable {
+                next: vec![Tile::Occupied, Tile::Empty],
+            },
+            occupied: RuleTable { next: occupied },
+            floor: RuleTable {
+                next: vec![Tile::Floor],
+            },
         }
-        star
     }
 
-    fn round(&self, tolerance: i32, range: bool) -> Self {
-        let mut next = self.clone();
-        for y in 0..self.size_y {
-            for x in 0..self.size_x {
-                let i = (x + y * self.size_x) as usize;
-                if self.layout[i] == Tile::Empty {
-                    if self
-                        .star(x, y, range)
-                        .iter()
-                        .filter(|x| **x == &Tile::Occupied)
-                        .count()
-                        == 0
-                    {
-                        next.layout[i] = Tile::Occupied;
-                    }
-                } else if self.layout[i] == Tile::Occupied {
-                    if self
-                        .star(x, y, range)
-                        .iter()
-                        .filter(|x| **x == &Tile::Occupied)
-                        .count()
-                        >= tolerance as usize
-                    {
-                        next.layout[i] = Tile::Empty;
-                    }
-                }
+    fn table(&self, tile: Tile) -> &RuleTable {
+        match tile {
+            Tile::Empty => &self.empty,
+            Tile::Occupied => &self.occupied,
+            Tile::Floor => &self.floor,
+        }
+    }
+}
+
+/// A cellular automaton over the tiles of a ferry, with the neighbour lists
+/// of every tile computed once up front.
+struct Automaton {
+    neighbours: Vec<Vec<usize>>,
+    rules: Rules,
+}
+
+impl Automaton {
+    fn new(ferry: &Ferry, neighborhood: &dyn Neighborhood, rules: Rules) -> Self {
+        let mut neighbours = vec![];
+        for y in 0..ferry.size_y {
+            for x in 0..ferry.size_x {
+                neighbours.push(neighborhood.neighbours(ferry, x, y));
             }
         }
-        next
+        Automaton { neighbours, rules }
+    }
+
+    fn round(&self, ferry: &Ferry) -> Ferry {
+        let layout = ferry
+            .layout
+            .iter()
+            .zip(self.neighbours.iter())
+            .map(|(&tile, neighbours)| {
+                let occupied = neighbours
+                    .iter()
+                    .filter(|&&i| ferry.layout[i] == Tile::Occupied)
+                    .count();
+                self.rules.table(tile).next(occupied).unwrap_or(tile)
+            })
+            .collect();
+        Ferry {
+            layout,
+            ..ferry.clone()
+        }
     }
 }
 
-fn engine(ferry: Ferry, tol: i32, range: bool) -> (i32, Ferry) {
-    let mut i = 0;
-    let mut next = ferry.clone();
+/// Where a simulation settles: after `rounds` rounds the layout starts
+/// repeating every `period` rounds, a period of 1 being a fixpoint.
+struct Run {
+    rounds: usize,
+    period: usize,
+    ferry: Ferry,
+}
+
+fn engine(ferry: Ferry, automaton: &Automaton) -> Run {
+    record(ferry, automaton, &mut |_| ())
+}
+
+/// Runs the simulation like `engine`, handing every distinct layout to
+/// `on_round` in order, starting with the initial one.
+fn record(ferry: Ferry, automaton: &Automaton, on_round: &mut dyn FnMut(&Ferry)) -> Run {
+    let mut seen = HashMap::new();
+    let mut next = ferry;
+    let mut round = 0;
     loop {
-        let prev = next.clone();
-        next = next.round(tol, range);
-        if next == prev {
-            break;
+        if let Some(&first) = seen.get(&next.layout) {
+            return Run {
+                rounds: first,
+                period: round - first,
+                ferry: next,
+            };
+        }
+        on_round(&next);
+        let prev = next;
+        next = automaton.round(&prev);
+        seen.insert(prev.layout, round);
+        round += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameFormat {
+    Text,
+    Pgm,
+    Ppm,
+}
+
+impl FrameFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            FrameFormat::Text => "txt",
+            FrameFormat::Pgm => "pgm",
+            FrameFormat::Ppm => "ppm",
+        }
+    }
+}
+
+/// Encodes a layout as a binary PGM or PPM image, each tile drawn as a
+/// `scale` by `scale` square.
+fn to_image(ferry: &Ferry, format: FrameFormat, scale: u32) -> Vec<u8> {
+    let color = |tile: Tile| -> [u8; 3] {
+        match tile {
+            Tile::Floor => [32, 32, 32],
+            Tile::Empty => [64, 192, 64],
+            Tile::Occupied => [224, 48, 48],
+        }
+    };
+    let gray = |tile: Tile| -> u8 {
+        match tile {
+            Tile::Floor => 0,
+            Tile::Empty => 255,
+            Tile::Occupied => 128,
+        }
+    };
+    let magic = if format == FrameFormat::Ppm {
+        "P6"
+    } else {
+        "P5"
+    };
+    let (width, height) = (ferry.size_x * scale, ferry.size_y * scale);
+    let mut image = format!("{}\n{} {}\n255\n", magic, width, height).into_bytes();
+    for py in 0..height {
+        for px in 0..width {
+            let i = ferry
+                .index((px / scale) as i32, (py / scale) as i32)
+                .unwrap();
+            let tile = ferry.layout[i];
+            match format {
+                FrameFormat::Ppm => image.extend(color(tile)),
+                _ => image.push(gray(tile)),
+            }
+        }
+    }
+    image
+}
+
+/// Writes each frame to `dir` as `frame_0000.<ext>`, `frame_0001.<ext>`...
+fn write_frames(frames: &[Ferry], dir: &Path, format: FrameFormat, scale: u32) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(format!("frame_{:04}.{}", i, format.extension()));
+        let bytes = match format {
+            FrameFormat::Text => frame.to_string().into_bytes(),
+            _ => to_image(frame, format, scale),
+        };
+        fs::write(path, bytes)?;
+    }
+    Ok(())
+}
+
+/// Plays the frames back in the terminal, redrawing in place.
+fn replay(frames: &[Ferry], delay: Duration) {
+    for (i, frame) in frames.iter().enumerate() {
+        println!("\x1b[2J\x1b[H{}round {}/{}", frame, i, frames.len() - 1);
+        io::stdout().flush().unwrap();
+        thread::sleep(delay);
+    }
+}
+
+fn parse_arg<T: FromStr>(args: &mut impl Iterator<Item = String>, name: &str) -> T {
+    match args.next().map(|raw| raw.parse()) {
+        Some(Ok(value)) => value,
+        _ => {
+            println!("{} needs a valid value", name);
+            process::exit(1);
         }
-        i += 1;
     }
-    (i, next)
 }
 
 fn main() {
+    let mut sight = false;
+    let mut format = None;
+    let mut play = false;
+    let mut out = PathBuf::from("frames");
+    let mut delay = 100;
+    let mut scale = 4;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sight" => sight = true,
+            "--record" => match args.next().as_deref() {
+                Some("text") => format = Some(FrameFormat::Text),
+                Some("pgm") => format = Some(FrameFormat::Pgm),
+                Some("ppm") => format = Some(FrameFormat::Ppm),
+                Some("replay") => play = true,
+                _ => {
+                    println!("--record needs one of text, pgm, ppm, replay");
+                    process::exit(1);
+                }
+            },
+            "--out" => out = parse_arg(&mut args, "--out"),
+            "--delay" => delay = parse_arg(&mut args, "--delay"),
+            "--scale" => scale = parse_arg::<u32>(&mut args, "--scale").max(1),
+            _ => {
+                println!("Unknown argument '{}'", arg);
+                process::exit(1);
+            }
+        }
+    }
+
     let raw = fs::read_to_string("input.txt").unwrap();
     let ferry = raw.parse::<Ferry>().unwrap();
-    let (i, no_range) = engine(ferry.clone(), 4, false);
-    println!("Occupied seats after {} rounds: {}", i, no_range.occupied());
-    let (i, range) = engine(ferry.clone(), 5, true);
-    println!("Occupied seats after {} rounds: {}", i, range.occupied());
+
+    if format.is_some() || play {
+        let automaton = if sight {
+            Automaton::new(&ferry, &LineOfSight, Rules::seating(5))
+        } else {
+            Automaton::new(&ferry, &Adjacent, Rules::seating(4))
+        };
+        let mut frames = vec![];
+        record(ferry, &automaton, &mut |round| frames.push(round.clone()));
+        if play {
+            replay(&frames, Duration::from_millis(delay));
+        }
+        if let Some(format) = format {
+            if let Err(err) = write_frames(&frames, &out, format, scale) {
+                println!("Failed to write the frames to {}. {}", out.display(), err);
+                process::exit(1);
+            }
+            println!("Wrote {} frames to {}", frames.len(), out.display());
+        }
+        return;
+    }
+
+    let adjacent = Automaton::new(&ferry, &Adjacent, Rules::seating(4));
+    let run = engine(ferry.clone(), &adjacent);
+    println!(
+        "Occupied seats after {} rounds: {} (period {})",
+        run.rounds,
+        run.ferry.occupied(),
+        run.period
+    );
+    let line_of_sight = Automaton::new(&ferry, &LineOfSight, Rules::seating(5));
+    let run = engine(ferry.clone(), &line_of_sight);
+    println!(
+        "Occupied seats after {} rounds: {} (period {})",
+        run.rounds,
+        run.ferry.occupied(),
+        run.period
+    );
 }
 
 #[cfg(test)]
@@ -194,23 +462,84 @@ mod test {
     #[test]
     fn one_round() {
         if let Ok(ferry) = INPUT_0.parse::<Ferry>() {
-            assert_eq!(ferry.round(4, false).occupied(), 71);
+            let automaton = Automaton::new(&ferry, &Adjacent, Rules::seating(4));
+            assert_eq!(automaton.round(&ferry).occupied(), 71);
         }
     }
 
     #[test]
     fn engine_no_range() {
         if let Ok(ferry) = INPUT_0.parse::<Ferry>() {
-            let (_, ferry) = engine(ferry, 4, false);
-            assert_eq!(ferry.occupied(), 37);
+            let automaton = Automaton::new(&ferry, &Adjacent, Rules::seating(4));
+            let run = engine(ferry, &automaton);
+            assert_eq!(run.ferry.occupied(), 37);
+            assert_eq!(run.period, 1);
         }
     }
 
     #[test]
     fn engine_ranged() {
         if let Ok(ferry) = INPUT_0.parse::<Ferry>() {
-            let (_, ferry) = engine(ferry, 5, true);
-            assert_eq!(ferry.occupied(), 26);
+            let automaton = Automaton::new(&ferry, &LineOfSight, Rules::seating(5));
+            let run = engine(ferry, &automaton);
+            assert_eq!(run.ferry.occupied(), 26);
+            assert_eq!(run.rounds, 6);
         }
     }
+
+    #[test]
+    fn cycles_and_custom_offsets() {
+        // a lone seat with no neighbours flips every round
+        let ferry = "L".parse::<Ferry>().unwrap();
+        let rules = Rules {
+            empty: RuleTable {
+                next: vec![Tile::Occupied],
+            },
+            occupied: RuleTable {
+                next: vec![Tile::Empty],
+            },
+            floor: RuleTable {
+                next: vec![Tile::Floor],
+            },
+        };
+        let run = engine(ferry.clone(), &Automaton::new(&ferry, &Adjacent, rules));
+        assert_eq!((run.rounds, run.period), (0, 2));
+
+        // empty tables keep every tile as it is
+        let ferry = "L#.\n#L#".parse::<Ferry>().unwrap();
+        let rules = Rules {
+            empty: RuleTable { next: vec![] },
+            occupied: RuleTable { next: vec![] },
+            floor: RuleTable { next: vec![] },
+        };
+        let run = engine(ferry.clone(), &Automaton::new(&ferry, &Adjacent, rules));
+        assert_eq!((run.rounds, run.period), (0, 1));
+        assert_eq!(run.ferry.layout, ferry.layout);
+
+        let ferry = "L.L\n...\nL.L".parse::<Ferry>().unwrap();
+        let two_away = Offsets(vec![(2, 0), (-2, 0), (0, 2), (0, -2)]);
+        assert_eq!(two_away.neighbours(&ferry, 0, 0), vec![2, 6]);
+        assert_eq!(LineOfSight.neighbours(&ferry, 0, 0), vec![2, 6, 8]);
+        assert!(Adjacent.neighbours(&ferry, 0, 0).len() == 3);
+    }
+
+    #[test]
+    fn recorded_frames() {
+        let ferry = INPUT_0.parse::<Ferry>().unwrap();
+        let automaton = Automaton::new(&ferry, &Adjacent, Rules::seating(4));
+        let mut frames = vec![];
+        let run = record(ferry.clone(), &automaton, &mut |f| frames.push(f.clone()));
+        assert_eq!(frames.len(), run.rounds + run.period);
+        assert!(frames[0] == ferry);
+        assert_eq!(frames.last().unwrap().occupied(), 37);
+
+        let small = "L.\n#L".parse::<Ferry>().unwrap();
+        assert_eq!(
+            to_image(&small, FrameFormat::Pgm, 1),
+            b"P5\n2 2\n255\n\xff\x00\x80\xff".to_vec()
+        );
+        let ppm = to_image(&small, FrameFormat::Ppm, 2);
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), "P6\n4 4\n255\n".len() + 4 * 4 * 3);
+    }
 }
@@ -1,5 +1,8 @@
 use core::str::FromStr;
+use std::env;
+use std::fmt;
 use std::fs;
+use std::process;
 
 #[derive(Clone)]
 enum ActCode {
@@ -47,6 +50,21 @@ impl FromStr for Action {
     }
 }
 
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = match self.code {
+            ActCode::North => "N",
+            ActCode::South => "S",
+            ActCode::East => "E",
+            ActCode::West => "W",
+            ActCode::Forward => "F",
+            ActCode::Left => "L",
+            ActCode::Right => "R",
+        };
+        write!(f, "{}{}", code, self.amt)
+    }
+}
+
 #[derive(Clone)]
 struct Instructions {
     actions: Vec<Action>,
@@ -74,23 +92,23 @@ struct Heading {
 
 impl Default for Heading {
     fn default() -> Self {
-        Heading::from_angle(0)
+        Heading::EAST
     }
 }
 
 impl Heading {
-    fn from_angle(angle: i32) -> Heading {
-        let clamped = if angle % 360 < 0 {
-            angle % 360 + 360
-        } else {
-            angle % 360
-        };
-        match clamped {
-            0 => Heading { x: 1, y: 0 },
-            90 => Heading { x: 0, y: 1 },
-            180 => Heading { x: -1, y: 0 },
-            270 => Heading { x: 0, y: -1 },
-            _ => unreachable!(),
+    const EAST: Heading = Heading { x: 1, y: 0 };
+    const NORTH: Heading = Heading { x: 0, y: 1 };
+    const WEST: Heading = Heading { x: -1, y: 0 };
+    const SOUTH: Heading = Heading { x: 0, y: -1 };
+
+    fn from_angle(angle: i32) -> Result<Heading, String> {
+        match angle.rem_euclid(360) {
+            0 => Ok(Heading::EAST),
+            90 => Ok(Heading::NORTH),
+            180 => Ok(Heading::WEST),
+            270 => Ok(Heading::SOUTH),
+            _ => Err(format!("{} degrees is not a multiple of 90", angle.abs())),
         }
     }
 
@@ -104,14 +122,28 @@ impl Heading {
         }
     }
 
-    fn turn(&self, angle: i32) -> Heading {
-        Heading::from_angle(angle + self.to_angle())
+    /// Turns by `angle`, which is checked on its own so a bad turn is
+    /// reported by the amount asked for.
+    fn turn(&self, angle: i32) -> Result<Heading, String> {
+        let by = Heading::from_angle(angle)?;
+        Heading::from_angle(by.to_angle() + self.to_angle())
     }
 }
 
-trait Boat {
-    fn cruise(&self, action: &Action) -> Self;
+/// Where a boat is after an action, with its heading or its waypoint
+/// (relative to the boat) depending on how it steers.
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+    x: i32,
+    y: i32,
+    heading: Option<Heading>,
+    waypoint: Option<Waypoint>,
+}
+
+trait Boat: Sized {
+    fn cruise(&self, action: &Action) -> Result<Self, String>;
     fn distance(&self) -> i32;
+    fn snapshot(&self) -> Snapshot;
 }
 
 #[derive(Clone, Default, Debug, Eq, PartialEq)]
@@ -122,30 +154,39 @@ struct SimpleBoat {
 }
 
 impl Boat for SimpleBoat {
-    fn cruise(&self, action: &Action) -> Self {
-        match action.code {
+    fn cruise(&self, action: &Action) -> Result<Self, String> {
+        Ok(match action.code {
             ActCode::Forward => self.forward(action.amt, &self.heading),
-            ActCode::Right => self.turn(-action.amt),
-            ActCode::Left => self.turn(action.amt),
-            ActCode::East => self.forward(action.amt, &Heading::from_angle(0)),
-            ActCode::North => self.forward(action.amt, &Heading::from_angle(90)),
-            ActCode::West => self.forward(action.amt, &Heading::from_angle(180)),
-            ActCode::South => self.forward(action.amt, &Heading::from_angle(270)),
-        }
+            ActCode::Right => self.turn(-action.amt)?,
+            ActCode::Left => self.turn(action.amt)?,
+            ActCode::East => self.forward(action.amt, &Heading::EAST),
+            ActCode::North => self.forward(action.amt, &Heading::NORTH),
+            ActCode::West => self.forward(action.amt, &Heading::WEST),
+            ActCode::South => self.forward(action.amt, &Heading::SOUTH),
+        })
     }
 
     fn distance(&self) -> i32 {
         self.x.abs() + self.y.abs()
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            x: self.x,
+            y: self.y,
+            heading: Some(self.heading.clone()),
+            waypoint: None,
+        }
+    }
 }
 
 impl SimpleBoat {
-    fn turn(&self, angle: i32) -> SimpleBoat {
-        SimpleBoat {
+    fn turn(&self, angle: i32) -> Result<SimpleBoat, String> {
+        Ok(SimpleBoat {
             x: self.x,
             y: self.y,
-            heading: self.heading.turn(angle),
-        }
+            heading: self.heading.turn(angle)?,
+        })
     }
 
     fn forward(&self, amt: i32, head: &Heading) -> SimpleBoat {
@@ -170,30 +211,25 @@ impl Default for Waypoint {
 }
 
 impl Waypoint {
-    fn rotate(&self, angle: i32) -> Waypoint {
-        let clamped = if angle % 360 < 0 {
-            angle % 360 + 360
-        } else {
-            angle % 360
-        };
-        match clamped {
-            0 => Waypoint {
+    fn rotate(&self, angle: i32) -> Result<Waypoint, String> {
+        match angle.rem_euclid(360) {
+            0 => Ok(Waypoint {
                 x: self.x,
                 y: self.y,
-            },
-            90 => Waypoint {
+            }),
+            90 => Ok(Waypoint {
                 x: -self.y,
                 y: self.x,
-            },
-            180 => Waypoint {
+            }),
+            180 => Ok(Waypoint {
                 x: -self.x,
                 y: -self.y,
-            },
-            270 => Waypoint {
+            }),
+            270 => Ok(Waypoint {
                 x: self.y,
                 y: -self.x,
-            },
-            _ => unreachable!(),
+            }),
+            _ => Err(format!("{} degrees is not a multiple of 90", angle.abs())),
         }
     }
 }
@@ -206,21 +242,30 @@ struct WaypointBoat {
 }
 
 impl Boat for WaypointBoat {
-    fn cruise(&self, action: &Action) -> Self {
-        match action.code {
+    fn cruise(&self, action: &Action) -> Result<Self, String> {
+        Ok(match action.code {
             ActCode::Forward => self.forward(action.amt),
-            ActCode::Right => self.rotate_wp(-action.amt),
-            ActCode::Left => self.rotate_wp(action.amt),
-            ActCode::East => self.move_wp(action.amt, &Heading::from_angle(0)),
-            ActCode::North => self.move_wp(action.amt, &Heading::from_angle(90)),
-            ActCode::West => self.move_wp(action.amt, &Heading::from_angle(180)),
-            ActCode::South => self.move_wp(action.amt, &Heading::from_angle(270)),
-        }
+            ActCode::Right => self.rotate_wp(-action.amt)?,
+            ActCode::Left => self.rotate_wp(action.amt)?,
+            ActCode::East => self.move_wp(action.amt, &Heading::EAST),
+            ActCode::North => self.move_wp(action.amt, &Heading::NORTH),
+            ActCode::West => self.move_wp(action.amt, &Heading::WEST),
+            ActCode::South => self.move_wp(action.amt, &Heading::SOUTH),
+        })
     }
 
     fn distance(&self) -> i32 {
         self.x.abs() + self.y.abs()
     }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            x: self.x,
+            y: self.y,
+            heading: None,
+            waypoint: Some(self.waypoint.clone()),
+        }
+    }
 }
 
 impl WaypointBoat {
@@ -232,12 +277,12 @@ impl WaypointBoat {
         }
     }
 
-    fn rotate_wp(&self, angle: i32) -> WaypointBoat {
-        WaypointBoat {
+    fn rotate_wp(&self, angle: i32) -> Result<WaypointBoat, String> {
+        Ok(WaypointBoat {
             x: self.x,
             y: self.y,
-            waypoint: self.waypoint.rotate(angle),
-        }
+            waypoint: self.waypoint.rotate(angle)?,
+        })
     }
 
     fn move_wp(&self, amt: i32, head: &Heading) -> WaypointBoat {
@@ -261,29 +306,153 @@ impl<T: Boat> Navigation<T>
 where
     T: Clone,
 {
-    fn cruise(&self) -> T {
+    fn cruise(&self) -> Result<T, String> {
         self.instructions
             .actions
             .iter()
-            .fold(self.boat.clone(), |acc, i| acc.cruise(i))
+            .try_fold(self.boat.clone(), |acc, i| {
+                acc.cruise(i).map_err(|err| format!("{}: {}", i, err))
+            })
+    }
+
+    /// The state of the boat before the first action and after each one.
+    fn trace(&self) -> Result<Vec<Snapshot>, String> {
+        let mut boat = self.boat.clone();
+        let mut trace = vec![boat.snapshot()];
+        for action in self.instructions.actions.iter() {
+            boat = boat
+                .cruise(action)
+                .map_err(|err| format!("{}: {}", action, err))?;
+            trace.push(boat.snapshot());
+        }
+        Ok(trace)
+    }
+}
+
+/// Plots a trace as an SVG image: the course of the boat as a polyline,
+/// the course of the waypoint as a dashed path, the axes through the
+/// starting point and the final Manhattan distance.
+fn to_svg(trace: &[Snapshot]) -> String {
+    const SIZE: f64 = 800.0;
+    const MARGIN: f64 = 40.0;
+
+    let mut points: Vec<(i32, i32)> = vec![(0, 0)];
+    points.extend(trace.iter().map(|s| (s.x, s.y)));
+    let waypoints: Vec<(i32, i32)> = trace
+        .iter()
+        .filter_map(|s| s.waypoint.as_ref().map(|w| (s.x + w.x, s.y + w.y)))
+        .collect();
+    points.extend(waypoints.iter());
+
+    let min_x = points.iter().map(|p| p.0).min().unwrap();
+    let max_x = points.iter().map(|p| p.0).max().unwrap();
+    let min_y = points.iter().map(|p| p.1).min().unwrap();
+    let max_y = points.iter().map(|p| p.1).max().unwrap();
+    let span = (max_x - min_x).max(max_y - min_y).max(1) as f64;
+    let scale = (SIZE - 2.0 * MARGIN) / span;
+    // north is up, so y grows the other way in the image
+    let px = |x: i32| MARGIN + (x - min_x) as f64 * scale;
+    let py = |y: i32| MARGIN + (max_y - y) as f64 * scale;
+    let path = |points: &[(i32, i32)]| -> String {
+        points
+            .iter()
+            .map(|&(x, y)| format!("{:.1},{:.1}", px(x), py(y)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    let last = trace.last().map_or((0, 0), |s| (s.x, s.y));
+    let distance = last.0.abs() + last.1.abs();
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+        SIZE
+    );
+    svg += "  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n";
+    svg += &format!(
+        "  <line x1=\"0\" y1=\"{0:.1}\" x2=\"{1}\" y2=\"{0:.1}\" stroke=\"gray\"/>\n",
+        py(0),
+        SIZE
+    );
+    svg += &format!(
+        "  <line x1=\"{0:.1}\" y1=\"0\" x2=\"{0:.1}\" y2=\"{1}\" stroke=\"gray\"/>\n",
+        px(0),
+        SIZE
+    );
+    svg += &format!(
+        "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"gray\">E</text>\n",
+        SIZE - 16.0,
+        py(0) - 4.0
+    );
+    svg += &format!(
+        "  <text x=\"{:.1}\" y=\"14\" fill=\"gray\">N</text>\n",
+        px(0) + 4.0
+    );
+    if !waypoints.is_empty() {
+        svg += &format!(
+            "  <path d=\"M {}\" fill=\"none\" stroke=\"orange\" stroke-dasharray=\"4 4\"/>\n",
+            path(&waypoints)
+        );
     }
+    let course: Vec<(i32, i32)> = trace.iter().map(|s| (s.x, s.y)).collect();
+    svg += &format!(
+        "  <polyline points=\"{}\" fill=\"none\" stroke=\"navy\" stroke-width=\"2\"/>\n",
+        path(&course)
+    );
+    svg += &format!(
+        "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"navy\"/>\n",
+        px(last.0),
+        py(last.1)
+    );
+    svg += &format!(
+        "  <text x=\"10\" y=\"{:.1}\">Manhattan distance: {} ({}, {})</text>\n",
+        SIZE - 10.0,
+        distance,
+        last.0,
+        last.1
+    );
+    svg + "</svg>\n"
 }
 
 fn main() {
     let input = fs::read_to_string("input.txt").unwrap();
     let instructions = input.parse::<Instructions>().unwrap();
-    let nav = Navigation {
+    let simple = Navigation {
         instructions: instructions.clone(),
         boat: SimpleBoat::default(),
     };
-    let boat = nav.cruise();
-    println!("the boat made it this far: {}", boat.distance());
-    let nav = Navigation {
+    let waypoint = Navigation {
         instructions,
         boat: WaypointBoat::default(),
     };
-    let boat = nav.cruise();
-    println!("the waypoint boat made it this far: {}", boat.distance());
+
+    let mut args = env::args().skip(1);
+    if let Some(arg) = args.next() {
+        let trace = match (arg.as_str(), args.next().as_deref()) {
+            ("--svg", Some("simple")) => simple.trace(),
+            ("--svg", Some("waypoint")) => waypoint.trace(),
+            _ => {
+                println!("Usage: day12 [--svg simple|waypoint]");
+                process::exit(1);
+            }
+        };
+        match trace {
+            Ok(trace) => print!("{}", to_svg(&trace)),
+            Err(err) => {
+                println!("Failed to navigate. {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    match simple.cruise() {
+        Ok(boat) => println!("the boat made it this far: {}", boat.distance()),
+        Err(err) => println!("the boat got lost: {}", err),
+    }
+    match waypoint.cruise() {
+        Ok(boat) => println!("the waypoint boat made it this far: {}", boat.distance()),
+        Err(err) => println!("the waypoint boat got lost: {}", err),
+    }
 }
 
 #[cfg(test)]
@@ -309,12 +478,12 @@ mod test {
             instructions: INPUT_0.parse::<Instructions>().unwrap(),
             boat: SimpleBoat::default(),
         };
-        let boat = nav.cruise();
+        let boat = nav.cruise().unwrap();
         assert_eq!(
             SimpleBoat {
                 x: 17,
                 y: -8,
-                heading: Heading::from_angle(270)
+                heading: Heading::from_angle(270).unwrap()
             },
             boat,
         );
@@ -327,7 +496,57 @@ mod test {
             instructions: INPUT_0.parse::<Instructions>().unwrap(),
             boat: WaypointBoat::default(),
         };
-        let boat = nav.cruise();
+        let boat = nav.cruise().unwrap();
         assert_eq!(boat.distance(), 286);
     }
+
+    #[test]
+    fn traces() {
+        let nav = Navigation {
+            instructions: INPUT_0.parse::<Instructions>().unwrap(),
+            boat: WaypointBoat::default(),
+        };
+        let trace = nav.trace().unwrap();
+        assert_eq!(trace.len(), 6);
+        assert_eq!((trace[1].x, trace[1].y), (100, 10));
+        assert_eq!(trace[4].waypoint, Some(Waypoint { x: 4, y: -10 }));
+        let svg = to_svg(&trace);
+        assert!(svg.contains("<polyline"));
+        assert!(svg.contains("stroke-dasharray"));
+        assert!(svg.contains("Manhattan distance: 286"));
+
+        let nav = Navigation {
+            instructions: INPUT_0.parse::<Instructions>().unwrap(),
+            boat: SimpleBoat::default(),
+        };
+        let trace = nav.trace().unwrap();
+        assert_eq!(trace[5].heading, Some(Heading::SOUTH));
+        assert!(!to_svg(&trace).contains("stroke-dasharray"));
+    }
+
+    #[test]
+    fn bad_angles() {
+        assert_eq!(Heading::from_angle(-90), Ok(Heading::SOUTH));
+        assert_eq!(
+            Heading::from_angle(45),
+            Err("45 degrees is not a multiple of 90".to_string())
+        );
+        assert!(Heading::SOUTH.turn(-135).is_err());
+        let nav = Navigation {
+            instructions: "F10\nL90\nR45".parse::<Instructions>().unwrap(),
+            boat: SimpleBoat::default(),
+        };
+        assert_eq!(
+            nav.cruise().unwrap_err(),
+            "R45: 45 degrees is not a multiple of 90"
+        );
+        let nav = Navigation {
+            instructions: "F10\nL100".parse::<Instructions>().unwrap(),
+            boat: WaypointBoat::default(),
+        };
+        assert_eq!(
+            nav.trace().unwrap_err(),
+            "L100: 100 degrees is not a multiple of 90"
+        );
+    }
 }
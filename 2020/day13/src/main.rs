@@ -1,4 +1,4 @@
-use std::{fs, str::FromStr, vec};
+use std::{fmt, fs, str::FromStr, vec};
 
 /// first part was really easy.
 
@@ -13,9 +13,13 @@ impl FromStr for Schedule {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (l, r) = s.split_once("\n").ok_or(())?;
+        let ids: Vec<i32> = r.split(",").filter_map(|x| x.parse::<i32>().ok()).collect();
+        if ids.iter().any(|&id| id <= 0) {
+            return Err(());
+        }
         Ok(Schedule {
             target: l.parse().or(Err(()))?,
-            ids: r.split(",").filter_map(|x| x.parse::<i32>().ok()).collect(),
+            ids,
         })
     }
 }
@@ -37,7 +41,7 @@ impl Schedule {
             .first_after_target()
             .iter()
             .enumerate()
-            .min_by(|x, y| x.1.cmp(&y.1))
+            .min_by(|x, y| x.1.cmp(y.1))
             .unwrap();
         self.ids[i] * (earliest - self.target as i32)
     }
@@ -51,88 +55,144 @@ struct Bus {
     off: i64,
 }
 
+#[cfg(test)]
 impl Bus {
     fn is_sync(&self, time: i64) -> bool {
         (time + self.off) % self.id == 0
     }
 }
 
-fn parse_buses(s: String) -> Vec<Bus> {
+/// Reads the buses and their offsets, skipping the `x` entries. A bus id
+/// is a period, so it must be positive.
+fn parse_buses(s: String) -> Result<Vec<Bus>, String> {
     s.trim_end_matches("\n")
         .split(",")
         .enumerate()
-        .filter_map(|(i, x)| {
-            dbg!(x);
-            if let Ok(n) = x.parse::<i64>() {
-                Some(Bus {
-                    id: n,
-                    off: i as i64,
-                })
-            } else {
-                None
-            }
+        .filter_map(|(i, x)| match x.parse::<i64>() {
+            Ok(n) if n > 0 => Some(Ok(Bus {
+                id: n,
+                off: i as i64,
+            })),
+            Ok(n) => Some(Err(format!("bus {} has id {}, ids must be positive", i, n))),
+            Err(_) => None,
         })
         .collect()
 }
 
-/// finds the first time a+rem is divisible by b.
-/// the iteration is minimal, max iterations is |b|
-/// s is the offset added to the iter var, is used to find the "wavelength"
-fn get_off(a: i64, b: i64, rem: i64, s: i64) -> Result<i64, String> {
-    for j in 0..b {
-        let i: i128 = (j * a + s).into();
-        if (i + rem as i128) % b as i128 == 0 {
-            return Ok(i as i64);
-        }
+/// Returns `(g, x, y)` such that `a * x + b * y = g = gcd(a, b)`.
+fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = ext_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
     }
-    Err(format!("not found number {a} and {b} with rem: {rem}"))
 }
 
-/// fold on buses to basically get 11...1 in base (buses_0, buses_1, ..., buses_n)
-fn slide(buses: Vec<Bus>) -> Result<i64, String> {
-    let mut rem = 0;
-    let mut delta = buses[0].id;
-    for i in 1..buses.len() {
-        let off = get_off(delta, buses[i].id, buses[i].off, 0)?;
-        // keep first offset, others are only used to find the wavelenght
-        if i == 1 {
-            rem += off;
+/// The times `t` such that `t = rem (mod modulus)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Congruence {
+    rem: i128,
+    modulus: i128,
+}
+
+impl Congruence {
+    /// The times at which the bus leaves `off` minutes after `t`.
+    fn of_bus(bus: &Bus) -> Self {
+        let modulus = bus.id as i128;
+        Congruence {
+            rem: (-(bus.off as i128)).rem_euclid(modulus),
+            modulus,
         }
-        for _ in 0..i32::MAX {
-            // cycle by previous wavelength until rem divides by buses.
-            if buses[0..i + 1].iter().all(|bus| bus.is_sync(rem)) {
-                break;
-            }
-            rem += delta;
+    }
+
+    /// Combines two congruences into one that holds exactly when both do.
+    /// The moduli don't need to be coprime: there is no solution when the
+    /// remainders disagree modulo their gcd. Fails with `Ok(None)` when
+    /// there is no solution and with `Err` on overflow.
+    fn merge(&self, other: &Congruence) -> Result<Option<Congruence>, SyncError> {
+        let (g, p, _) = ext_gcd(self.modulus, other.modulus);
+        let diff = other.rem - self.rem;
+        if diff % g != 0 {
+            return Ok(None);
         }
-        // calculate next wavelength
-        let next_delta = get_off(delta, buses[i].id, buses[i].off, off + delta)? - off;
-        delta = next_delta;
+        let step = other.modulus / g;
+        let k = ((diff / g).rem_euclid(step))
+            .checked_mul(p.rem_euclid(step))
+            .ok_or(SyncError::Overflow)?
+            % step;
+        let modulus = self.modulus.checked_mul(step).ok_or(SyncError::Overflow)?;
+        let rem = self.modulus * k + self.rem;
+        Ok(Some(Congruence {
+            rem: rem.rem_euclid(modulus),
+            modulus,
+        }))
     }
-    Ok(rem)
 }
 
-/// just check every i32 * first bus id as an easy optimization
-fn bruteforce(buses: Vec<Bus>) -> Result<i64, String> {
-    for i in 0..i32::MAX as i64 {
-        let j = i * buses[0].id;
-        if buses.iter().all(|bus| bus.is_sync(j)) {
-            return Ok(j);
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SyncError {
+    /// No time can satisfy both buses.
+    Conflict(Bus, Bus),
+    /// The period of the schedule doesn't fit in an i128.
+    Overflow,
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::Conflict(a, b) => write!(
+                f,
+                "bus {} at offset {} and bus {} at offset {} can never line up",
+                a.id, a.off, b.id, b.off
+            ),
+            SyncError::Overflow => write!(f, "the timestamp doesn't fit in an i128"),
         }
     }
-    Err("the timestamp is too big".to_string())
+}
+
+/// Finds the earliest time at which every bus leaves at its offset, by
+/// merging the congruence of each bus into the ones before it (generalized
+/// chinese remainder theorem).
+fn synchronise(buses: &[Bus]) -> Result<i128, SyncError> {
+    let mut acc = Congruence { rem: 0, modulus: 1 };
+    for (i, bus) in buses.iter().enumerate() {
+        let next = Congruence::of_bus(bus);
+        acc = match acc.merge(&next)? {
+            Some(merged) => merged,
+            None => {
+                // a system of congruences is solvable if and only if every
+                // pair is, so one of the previous buses clashes with this one
+                let other = buses[..i]
+                    .iter()
+                    .find(|other| matches!(Congruence::of_bus(other).merge(&next), Ok(None)))
+                    .unwrap();
+                return Err(SyncError::Conflict(other.clone(), bus.clone()));
+            }
+        };
+    }
+    Ok(acc.rem)
 }
 
 fn main() {
     // just panic if something fails
     let input = fs::read_to_string("input.txt").unwrap();
-    let sched = input.parse::<Schedule>().unwrap();
-    let score = sched.score();
-    println!("id * delta: {}", score);
+    match input.parse::<Schedule>() {
+        Ok(sched) => println!("id * delta: {}", sched.score()),
+        Err(()) => println!("the schedule needs a target time and positive bus ids"),
+    }
     let only_second = input.split_once("\n").unwrap().1;
-    let buses = parse_buses(only_second.to_string());
-    let early = slide(buses).unwrap();
-    println!("earliest: {}", early);
+    let buses = match parse_buses(only_second.to_string()) {
+        Ok(buses) => buses,
+        Err(err) => {
+            println!("no earliest time: {}", err);
+            return;
+        }
+    };
+    match synchronise(&buses) {
+        Ok(early) => println!("earliest: {}", early),
+        Err(err) => println!("no earliest time: {}", err),
+    }
 }
 
 #[cfg(test)]
@@ -158,6 +218,7 @@ mod test {
         let sched = INPUT_0.parse::<Schedule>().unwrap();
         assert_eq!(sched.target, 939);
         assert_eq!(sched.ids.len(), 5);
+        assert!("939\n7,0,x".parse::<Schedule>().is_err());
     }
 
     #[test]
@@ -166,33 +227,99 @@ mod test {
         assert_eq!(944, *sched.first_after_target().iter().min().unwrap());
     }
 
+    /// just check every i32 * first bus id as an easy optimization
+    fn bruteforce(buses: Vec<Bus>) -> Result<i64, String> {
+        for i in 0..i32::MAX as i64 {
+            let j = i * buses[0].id;
+            if buses.iter().all(|bus| bus.is_sync(j)) {
+                return Ok(j);
+            }
+        }
+        Err("the timestamp is too big".to_string())
+    }
+
+    fn slide(buses: Vec<Bus>) -> Result<i128, SyncError> {
+        synchronise(&buses)
+    }
+
+    fn oracle(buses: Vec<Bus>) -> Result<i128, SyncError> {
+        Ok(bruteforce(buses).unwrap() as i128)
+    }
+
     #[test]
     fn slideit_example() {
-        assert_eq!(Ok(1068781), slide(parse_buses(BUSES_0.to_string())));
-        assert_eq!(Ok(3417), slide(parse_buses(BUSES_1.to_string())));
-        assert_eq!(Ok(754018), slide(parse_buses(BUSES_2.to_string())));
-        assert_eq!(Ok(779210), slide(parse_buses(BUSES_3.to_string())));
-        assert_eq!(Ok(1261476), slide(parse_buses(BUSES_4.to_string())));
-        assert_eq!(Ok(1202161486), slide(parse_buses(BUSES_5.to_string())));
+        assert_eq!(
+            Ok(1068781),
+            slide(parse_buses(BUSES_0.to_string()).unwrap())
+        );
+        assert_eq!(Ok(3417), slide(parse_buses(BUSES_1.to_string()).unwrap()));
+        assert_eq!(Ok(754018), slide(parse_buses(BUSES_2.to_string()).unwrap()));
+        assert_eq!(Ok(779210), slide(parse_buses(BUSES_3.to_string()).unwrap()));
+        assert_eq!(
+            Ok(1261476),
+            slide(parse_buses(BUSES_4.to_string()).unwrap())
+        );
+        assert_eq!(
+            Ok(1202161486),
+            slide(parse_buses(BUSES_5.to_string()).unwrap())
+        );
     }
 
     #[test]
     fn slideit_versus() {
         assert_eq!(
-            bruteforce(parse_buses(BUSES_6.to_string())),
-            slide(parse_buses(BUSES_6.to_string()))
+            oracle(parse_buses(BUSES_6.to_string()).unwrap()),
+            slide(parse_buses(BUSES_6.to_string()).unwrap())
         );
         assert_eq!(
-            bruteforce(parse_buses(BUSES_7.to_string())),
-            slide(parse_buses(BUSES_7.to_string()))
+            oracle(parse_buses(BUSES_7.to_string()).unwrap()),
+            slide(parse_buses(BUSES_7.to_string()).unwrap())
         );
         assert_eq!(
-            bruteforce(parse_buses(BUSES_8.to_string())),
-            slide(parse_buses(BUSES_8.to_string()))
+            oracle(parse_buses(BUSES_8.to_string()).unwrap()),
+            slide(parse_buses(BUSES_8.to_string()).unwrap())
         );
         assert_eq!(
-            bruteforce(parse_buses(BUSES_9.to_string())),
-            slide(parse_buses(BUSES_9.to_string()))
+            oracle(parse_buses(BUSES_9.to_string()).unwrap()),
+            slide(parse_buses(BUSES_9.to_string()).unwrap())
         );
     }
+
+    #[test]
+    fn non_coprime() {
+        for buses in ["6,x,4", "4,x,x,x,x,x,6,x,10"] {
+            let buses = parse_buses(buses.to_string()).unwrap();
+            assert_eq!(oracle(buses.clone()), slide(buses));
+        }
+        let err = slide(parse_buses("5,6,4".to_string()).unwrap()).unwrap_err();
+        assert_eq!(
+            err,
+            SyncError::Conflict(Bus { id: 6, off: 1 }, Bus { id: 4, off: 2 })
+        );
+    }
+
+    #[test]
+    fn bad_ids() {
+        assert_eq!(
+            parse_buses("7,x,0".to_string()),
+            Err("bus 2 has id 0, ids must be positive".to_string())
+        );
+        assert!(parse_buses("7,-13".to_string()).is_err());
+        assert_eq!(
+            parse_buses("x,7".to_string()),
+            Ok(vec![Bus { id: 7, off: 1 }])
+        );
+    }
+
+    #[test]
+    fn huge_periods() {
+        // pairwise coprime primes whose product overflows an i64
+        let primes = "1000000007,1000000009,x,1000000021,1000000033";
+        let buses = parse_buses(primes.to_string()).unwrap();
+        let time = slide(buses.clone()).unwrap();
+        assert!(time > i64::MAX as i128);
+        for bus in buses {
+            assert_eq!((time + bus.off as i128) % bus.id as i128, 0);
+        }
+    }
 }
@@ -1,9 +1,91 @@
 use std::collections::HashMap;
+use std::env;
 use std::vec;
 use std::{fs, str::FromStr};
 
 trait Mask {
+    /// Lists every single address written and its value.
     fn apply(&self, addr: i64, num: i64) -> Vec<(i64, i64)>;
+
+    /// The addresses written as one pattern, and the value written.
+    fn write(&self, addr: i64, num: i64) -> (Pattern, i64);
+}
+
+/// A set of addresses given as a ternary pattern: the `floating` bits take
+/// every value, the other bits are the ones of `fixed`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Pattern {
+    fixed: i64,
+    floating: i64,
+}
+
+impl Pattern {
+    fn new(fixed: i64, floating: i64) -> Self {
+        Pattern {
+            fixed: fixed & !floating,
+            floating,
+        }
+    }
+
+    fn len(&self) -> i64 {
+        1 << self.floating.count_ones()
+    }
+
+    fn intersects(&self, other: &Pattern) -> bool {
+        let both_fixed = !self.floating & !other.floating;
+        (self.fixed ^ other.fixed) & both_fixed == 0
+    }
+
+    /// Splits the addresses of `self` that are not in `other` into disjoint
+    /// patterns: one for each floating bit of `self` that `other` fixes.
+    fn subtract(&self, other: &Pattern) -> Vec<Pattern> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+        let mut pieces = vec![];
+        let mut rest = *self;
+        let split = self.floating & !other.floating;
+        for i in (0..64).filter(|i| split >> i & 1 == 1) {
+            let bit = 1 << i;
+            rest.floating &= !bit;
+            // the half that disagrees with `other` on this bit is outside it
+            pieces.push(Pattern::new(
+                rest.fixed | (!other.fixed & bit),
+                rest.floating,
+            ));
+            rest.fixed |= other.fixed & bit;
+        }
+        pieces
+    }
+}
+
+/// Memory holding writes as disjoint address patterns, so that a write to
+/// 2^k addresses costs one pattern instead of 2^k entries.
+#[derive(Debug, Clone, Default)]
+struct SymbolicMemory {
+    writes: Vec<(Pattern, i64)>,
+}
+
+impl SymbolicMemory {
+    fn write(&mut self, pattern: Pattern, num: i64) {
+        self.writes = self
+            .writes
+            .iter()
+            .flat_map(|&(old, value)| {
+                old.subtract(&pattern)
+                    .into_iter()
+                    .map(move |piece| (piece, value))
+            })
+            .collect();
+        self.writes.push((pattern, num));
+    }
+
+    fn sum(&self) -> i64 {
+        self.writes
+            .iter()
+            .map(|(pattern, value)| pattern.len() * value)
+            .sum()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
@@ -32,6 +114,10 @@ impl Mask for SimpleMask {
     fn apply(&self, addr: i64, num: i64) -> Vec<(i64, i64)> {
         vec![(addr, (num | self.hi) & !self.lo)]
     }
+
+    fn write(&self, addr: i64, num: i64) -> (Pattern, i64) {
+        (Pattern::new(addr, 0), (num | self.hi) & !self.lo)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
@@ -75,17 +161,21 @@ impl Mask for FloatingMask {
         floated.sort();
         for i in 0..(2_i64.pow(floated.len() as u32)) {
             let mut newmask = masked;
-            for j in 0..floated.len() {
+            for (j, bit) in floated.iter().enumerate() {
                 if (i >> j) & 1 == 1 {
-                    newmask |= 2_i64.pow(floated[j] as u32);
+                    newmask |= 2_i64.pow(*bit as u32);
                 } else {
-                    newmask &= !2_i64.pow(floated[j] as u32);
+                    newmask &= !2_i64.pow(*bit as u32);
                 }
             }
             ret.push((newmask, num));
         }
         ret
     }
+
+    fn write(&self, addr: i64, num: i64) -> (Pattern, i64) {
+        (Pattern::new(addr | self.hi, self.floating), num)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -123,7 +213,7 @@ where
 #[derive(Clone, Debug)]
 struct Program<T: Mask> {
     instr: Vec<Instruction<T>>,
-    mem: HashMap<i64, i64>,
+    mem: SymbolicMemory,
     mask: T,
 }
 
@@ -141,7 +231,7 @@ where
                 .split("\n")
                 .filter_map(|l| l.trim().parse::<Instruction<T>>().ok())
                 .collect(),
-            mem: HashMap::new(),
+            mem: SymbolicMemory::default(),
             mask: T::default(),
         })
     }
@@ -156,9 +246,8 @@ where
         match instr {
             Instruction::SetMask(m) => next.mask = m.clone(),
             Instruction::Write { addr, num } => {
-                for (a, v) in self.mask.apply(addr, num) {
-                    next.mem.insert(a, v);
-                }
+                let (pattern, value) = self.mask.write(addr, num);
+                next.mem.write(pattern, value);
             }
         };
         next.instr.remove(0);
@@ -166,11 +255,9 @@ where
     }
 
     fn pop(&self) -> Option<Program<T>> {
-        if let Some(instr) = self.instr.first() {
-            Some(self.clone().apply(instr.clone()))
-        } else {
-            None
-        }
+        self.instr
+            .first()
+            .map(|instr| self.clone().apply(instr.clone()))
     }
 
     fn exec(&self) -> Program<T> {
@@ -182,7 +269,21 @@ where
     }
 
     fn sum(&self) -> i64 {
-        self.mem.iter().fold(0, |acc, (_, v)| acc + v)
+        self.mem.sum()
+    }
+
+    /// Runs the remaining instructions writing every single address, as a
+    /// reference for the symbolic memory.
+    fn exec_expanded(&self) -> HashMap<i64, i64> {
+        let mut mem = HashMap::new();
+        let mut mask = self.mask.clone();
+        for instr in self.instr.iter() {
+            match instr {
+                Instruction::SetMask(m) => mask = m.clone(),
+                Instruction::Write { addr, num } => mem.extend(mask.apply(*addr, *num)),
+            }
+        }
+        mem
     }
 }
 
@@ -192,6 +293,10 @@ fn main() {
     println!("The simple sum is {}", program.exec().sum());
     let program: Program<FloatingMask> = input.parse().unwrap();
     println!("The floating sum is {}", program.exec().sum());
+    if env::args().any(|arg| arg == "--expand") {
+        let expanded: i64 = program.exec_expanded().values().sum();
+        println!("The floating sum listing every address is {}", expanded);
+    }
 }
 
 #[cfg(test)]
@@ -232,6 +337,61 @@ mod test {
         let program: Program<FloatingMask> = PROGRAM_FLOAT.parse().unwrap();
         assert_eq!(208, program.exec().sum());
     }
-}
 
-// first try second part!
+    #[test]
+    fn subtract_patterns() {
+        let all = Pattern::new(0, 0b111);
+        let pieces = all.subtract(&Pattern::new(0b010, 0b001));
+        assert_eq!(pieces.iter().map(|p| p.len()).sum::<i64>(), 6);
+        assert!(!pieces.iter().any(|p| p.intersects(&Pattern::new(0b011, 0))));
+        assert_eq!(Pattern::new(0b100, 0b011).subtract(&all), vec![]);
+        assert_eq!(
+            Pattern::new(0b100, 0).subtract(&Pattern::new(0, 0b011)),
+            vec![Pattern::new(0b100, 0)]
+        );
+    }
+
+    #[test]
+    fn symbolic_matches_expansion() {
+        // every pair of writes under every mask over the low 4 bits, so each
+        // way two floating patterns can overlap is covered
+        let masks: Vec<String> = (0..81)
+            .map(|mut n| {
+                let mut mask = "0".repeat(32);
+                for _ in 0..4 {
+                    mask.push(['0', '1', 'X'][n % 3]);
+                    n /= 3;
+                }
+                mask
+            })
+            .collect();
+        for first in masks.iter() {
+            for second in masks.iter() {
+                for addr in 0..16 {
+                    let program: Program<FloatingMask> = format!(
+                        "mask = {}\nmem[{}] = 1\nmask = {}\nmem[{}] = 2",
+                        first,
+                        addr,
+                        second,
+                        addr ^ 0b0110
+                    )
+                    .parse()
+                    .unwrap();
+                    let expanded: i64 = program.exec_expanded().values().sum();
+                    assert_eq!(program.exec().sum(), expanded);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wide_floating_masks() {
+        let program: Program<FloatingMask> = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+            mem[0] = 1
+            mask = 0000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+            mem[0] = 3"
+            .parse()
+            .unwrap();
+        assert_eq!(program.exec().sum(), (1 << 36) - (1 << 32) + 3 * (1 << 32));
+    }
+}
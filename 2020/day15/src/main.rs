@@ -1,116 +1,195 @@
-use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::process;
 use std::str::FromStr;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 struct Sequence {
-    nums: Vec<i64>,
+    nums: Vec<u32>,
 }
 
 impl FromStr for Sequence {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Sequence {
-            nums: s
-                .trim_end_matches("\n")
-                .split(",")
-                .map(|n| n.parse().unwrap())
-                .collect(),
-        })
+        let nums = s
+            .trim()
+            .split(',')
+            .map(|n| {
+                n.trim()
+                    .parse()
+                    .map_err(|err| format!("'{}' is not a starting number: {}", n, err))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        Ok(Sequence { nums })
     }
 }
 
-impl Sequence {
-    fn last(&self) -> i64 {
-        self.nums.last().unwrap().clone()
-    }
+/// Plays the memory game, yielding the number spoken at each turn starting
+/// with the starting numbers.
+///
+/// The turn each number was last spoken is kept in a flat table indexed by
+/// the number, 0 meaning never. A number spoken is always smaller than the
+/// current turn, so the table never outgrows the number of turns played.
+#[derive(Debug)]
+struct MemoryGame {
+    starting: Vec<u32>,
+    last_seen: Vec<u32>,
+    /// The number of turns played so far.
+    turn: u32,
+    /// The number to speak at the next turn.
+    next: u32,
+}
 
-    fn next(&self) -> Sequence {
-        let last = self.last();
-        let next = if let Some((i, _)) = self
-            .nums
-            .iter()
-            .enumerate()
-            .rev()
-            .skip(1)
-            .find(|(_, x)| x == &&last)
-        {
-            self.nums.len() as i64 - 1 - i as i64
-        } else {
-            0
-        };
-        Sequence {
-            nums: self.nums.iter().chain([&next]).cloned().collect(),
+impl MemoryGame {
+    fn new(seq: &Sequence) -> Self {
+        MemoryGame {
+            starting: seq.nums.clone(),
+            last_seen: vec![],
+            turn: 0,
+            next: seq.nums[0],
         }
     }
-}
 
-#[derive(Debug)]
-struct FastSequence {
-    past: HashMap<i64, i64>,
-    current: i64,
-    step: i64,
-}
+    /// Sets the table up front for a game of `turns` turns.
+    fn with_turns(seq: &Sequence, turns: u32) -> Self {
+        let mut game = Self::new(seq);
+        let largest = seq.nums.iter().copied().max().unwrap_or(0);
+        game.last_seen = vec![0; turns.max(largest + 1) as usize];
+        game
+    }
 
-impl FastSequence {
-    fn from_seq(seq: &Sequence) -> Self {
-        let mut fast = FastSequence {
-            past: HashMap::new(),
-            current: 0,
-            step: 0,
-        };
-        for (i, num) in seq.nums.iter().enumerate() {
-            fast.current = *num;
-            if i <= seq.nums.len() - 2 {
-                fast.step += 1;
-                fast.past.insert(*num, i as i64);
-            }
-        }
-        fast
+    /// The number spoken at `turn`, counting from 1.
+    fn spoken_at(seq: &Sequence, turn: u32) -> Option<u32> {
+        let turn = turn.checked_sub(1)?;
+        Self::with_turns(seq, turn).nth(turn as usize)
     }
 
-    fn next(&mut self) {
-        if let Some(last) = self.past.get_mut(&self.current) {
-            self.current = self.step - *last;
-            *last = self.step;
-        } else {
-            self.past.insert(self.current, self.step);
-            self.current = 0;
-        }
-        self.step += 1;
+    /// The size of the last seen table in bytes.
+    fn memory(&self) -> usize {
+        self.last_seen.len() * std::mem::size_of::<u32>()
     }
 }
 
-fn run(seq: &Sequence, max: i64) -> Sequence {
-    let mut curr = seq.clone();
-    for _ in 0..(max - seq.nums.len() as i64) {
-        curr = curr.next();
-    }
-    curr
+/// The peak resident set size of the process in bytes, as reported by the
+/// `VmHWM` line of `/proc/self/status`.
+fn peak_memory() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: usize = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
 }
 
-fn run_map(seq: &Sequence, max: i64) -> i64 {
-    let mut fast = FastSequence::from_seq(seq);
-    for _ in 0..(max - seq.nums.len() as i64) {
-        fast.next();
+impl Iterator for MemoryGame {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let spoken = self.next;
+        let index = spoken as usize;
+        if index >= self.last_seen.len() {
+            let len = (index + 1).max(self.last_seen.len() * 2);
+            self.last_seen.resize(len, 0);
+        }
+        let turn = self.turn.checked_add(1)?;
+        let last = std::mem::replace(&mut self.last_seen[index], turn);
+        self.next = match self.starting.get(turn as usize) {
+            Some(&num) => num,
+            None if last == 0 => 0,
+            None => turn - last,
+        };
+        self.turn = turn;
+        Some(spoken)
     }
-    fast.current
 }
 
 fn main() {
-    let seq: Sequence = fs::read_to_string("input.txt").unwrap().parse().unwrap();
-    println!("The 2020th number spoken is {}", run(&seq, 2020).last());
-    println!(
-        "The 30000000th number spoken is {}",
-        run_map(&seq, 30000000)
-    );
+    let mut path = "input.txt".to_string();
+    let mut turns = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                path = args.next().unwrap_or_else(|| {
+                    println!("--input needs a file");
+                    process::exit(1);
+                })
+            }
+            _ => match arg.parse::<u32>() {
+                Ok(turn) if turn > 0 => turns.push(turn),
+                _ => {
+                    println!("'{}' is not a turn, expected a positive number", arg);
+                    process::exit(1);
+                }
+            },
+        }
+    }
+
+    let seq: Sequence = fs::read_to_string(&path)
+        .map_err(|err| format!("Failed to open '{}'. {}", path, err))
+        .and_then(|raw| raw.parse())
+        .unwrap_or_else(|err| {
+            println!("{}", err);
+            process::exit(1);
+        });
+
+    if turns.is_empty() {
+        let spoken = |turn| MemoryGame::spoken_at(&seq, turn).unwrap();
+        println!("The 2020th number spoken is {}", spoken(2020));
+        println!("The 30000000th number spoken is {}", spoken(30000000));
+        return;
+    }
+
+    for turn in turns {
+        let start = Instant::now();
+        let mut game = MemoryGame::with_turns(&seq, turn);
+        let spoken = game.nth(turn as usize - 1).unwrap();
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("The number spoken at turn {} is {}", turn, spoken);
+        let (memory, label) = match peak_memory() {
+            Some(peak) => (peak, "peak memory"),
+            None => (game.memory(), "last seen table size"),
+        };
+        println!(
+            "  {:.2}s, {:.1}M turns/s, {:.1} MiB {}",
+            elapsed,
+            turn as f64 / elapsed / 1e6,
+            memory as f64 / (1 << 20) as f64,
+            label
+        );
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::*;
 
-    fn get_simple_examples() -> Vec<(String, i64)> {
+    /// Replays the whole game at every turn, as a reference for the game.
+    fn next(seq: &Sequence) -> Sequence {
+        let last = *seq.nums.last().unwrap();
+        let next = if let Some((i, _)) = seq
+            .nums
+            .iter()
+            .enumerate()
+            .rev()
+            .skip(1)
+            .find(|(_, x)| x == &&last)
+        {
+            (seq.nums.len() - 1 - i) as u32
+        } else {
+            0
+        };
+        Sequence {
+            nums: seq.nums.iter().chain([&next]).cloned().collect(),
+        }
+    }
+
+    fn get_simple_examples() -> Vec<(String, u32)> {
         vec![
             ("1,3,2".to_string(), 1),
             ("2,1,3".to_string(), 10),
@@ -121,7 +200,7 @@ mod test {
         ]
     }
 
-    fn get_hard_examples() -> Vec<(String, i64)> {
+    fn get_hard_examples() -> Vec<(String, u32)> {
         vec![
             ("0,3,6".to_string(), 175594),
             ("1,3,2".to_string(), 2578),
@@ -133,33 +212,48 @@ mod test {
         ]
     }
 
+    #[test]
     fn base_example() {
-        assert_eq![0, run(&"0,3,6".parse().unwrap(), 10).last()];
-        assert_eq![0, run_map(&"0,3,6".parse().unwrap(), 10)];
+        let seq: Sequence = "0,3,6".parse().unwrap();
+        let spoken: Vec<u32> = MemoryGame::new(&seq).take(10).collect();
+        assert_eq![spoken, vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]];
+        assert_eq![Some(0), MemoryGame::spoken_at(&seq, 10)];
+        assert_eq![Some(0), MemoryGame::spoken_at(&seq, 1)];
+        assert_eq![None, MemoryGame::spoken_at(&seq, 0)];
     }
 
     #[test]
     fn simple_examples() {
         for (ex, res) in get_simple_examples().iter() {
-            dbg!(ex);
             let seq: Sequence = ex.parse().unwrap();
             let mut curr = seq.clone();
-            let mut fast = FastSequence::from_seq(&seq);
-            for i in (seq.nums.len() as i64)..2020 {
-                curr = curr.next();
-                fast.next();
-                assert_eq![curr.last(), fast.current];
+            while curr.nums.len() < 2020 {
+                curr = next(&curr);
             }
-            assert_eq![*res, curr.last()];
-            assert_eq![*res, fast.current];
+            let spoken: Vec<u32> = MemoryGame::new(&seq).take(2020).collect();
+            assert_eq![curr.nums, spoken];
+            assert_eq![Some(*res), MemoryGame::spoken_at(&seq, 2020)];
         }
     }
 
     #[test]
     fn hard_examples() {
         for (ex, res) in get_hard_examples().iter() {
-            dbg!(ex);
-            assert_eq![res, &run_map(&ex.parse().unwrap(), 30000000)];
+            assert_eq![
+                Some(*res),
+                MemoryGame::spoken_at(&ex.parse().unwrap(), 30000000)
+            ];
         }
     }
+
+    #[test]
+    fn table_grows_on_demand() {
+        let seq: Sequence = "100000,1".parse().unwrap();
+        let mut game = MemoryGame::new(&seq);
+        assert_eq![game.nth(2), Some(0)];
+        assert_eq![game.memory(), 100001 * 4];
+        assert_eq![MemoryGame::with_turns(&seq, 10).memory(), 100001 * 4];
+        assert_eq![MemoryGame::with_turns(&seq, 200000).memory(), 200000 * 4];
+        assert!("1,,2".parse::<Sequence>().is_err());
+    }
 }
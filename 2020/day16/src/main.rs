@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug, fs, ops::Range, str::FromStr};
+use std::{env, fmt, fmt::Debug, fs, ops::Range, process, str::FromStr};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct Bound {
@@ -46,7 +46,7 @@ impl FromStr for Ticket {
 }
 
 impl Ticket {
-    fn get_invalid_value(&self, bounds: &Vec<Bound>) -> Option<i64> {
+    fn get_invalid_value(&self, bounds: &[Bound]) -> Option<i64> {
         for v in self.values.iter() {
             if !bounds.iter().any(|b| b.valid.iter().any(|r| r.contains(v))) {
                 return Some(*v);
@@ -130,65 +130,202 @@ impl From<&Reference> for FieldReference {
     }
 }
 
-impl FieldReference {
-    fn assign(&mut self, r: Reference) {
-        let mut map = HashMap::<u32, Vec<u32>>::new();
-        for i in 0..self.fields.len() {
-            for j in 0..r.bounds.len() {
-                if self.fields[i]
-                    .values
-                    .iter()
-                    .all(|x| r.bounds[j].valid.iter().any(|r| r.contains(x)))
-                {
-                    if let Some(ovf) = map.get_mut(&(j as u32)) {
-                        ovf.push(i as u32);
-                    } else {
-                        map.insert(j as u32, vec![i as u32]);
-                    }
+/// Why the fields could not be given one column each.
+#[derive(Debug, PartialEq, Eq)]
+enum AssignError {
+    /// No assignment exists, these fields are left without a column.
+    Unmatched(Vec<String>),
+    /// Several assignments fit the tickets, each giving the field name of
+    /// every column.
+    Ambiguous(Vec<Vec<Option<String>>>),
+}
+
+impl fmt::Display for AssignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssignError::Unmatched(names) => {
+                write!(f, "no column fits the fields {}", names.join(", "))
+            }
+            AssignError::Ambiguous(assignments) => {
+                write!(f, "{} assignments fit the tickets:", assignments.len())?;
+                for names in assignments {
+                    let names: Vec<&str> = names
+                        .iter()
+                        .map(|name| name.as_deref().unwrap_or("?"))
+                        .collect();
+                    write!(f, "\n  {}", names.join(", "))?;
                 }
+                Ok(())
             }
         }
+    }
+}
 
-        loop {
-            let most_constrained = map.iter().min_by(|(_, v), (_, w)| v.len().cmp(&w.len()));
-            if let Some((key, v)) = most_constrained {
-                assert_eq!(1, v.len());
-                self.fields[v[0] as usize].name = Some(r.bounds[*key as usize].name.clone());
-                map = map
-                    .iter()
-                    .filter(|(k, _)| *k != key)
-                    .map(|(k, w)| {
-                        (
-                            k.clone(),
-                            w.iter()
-                                .filter(|x| **x != v[0])
-                                .cloned()
-                                .collect::<Vec<u32>>(),
-                        )
+/// Looks for an augmenting path from `field` in Kuhn's algorithm, skipping
+/// the `used` columns.
+fn augment(
+    field: usize,
+    cands: &[Vec<usize>],
+    used: &[bool],
+    seen: &mut [bool],
+    owner: &mut [Option<usize>],
+) -> bool {
+    for &col in cands[field].iter() {
+        if used[col] || seen[col] {
+            continue;
+        }
+        seen[col] = true;
+        if owner[col].is_none_or(|other| augment(other, cands, used, seen, owner)) {
+            owner[col] = Some(field);
+            return true;
+        }
+    }
+    false
+}
+
+/// Matches as many fields as possible to distinct columns among their
+/// candidates, giving the column of each field.
+fn max_matching(cands: &[Vec<usize>], used: &[bool]) -> Vec<Option<usize>> {
+    let mut owner = vec![None; used.len()];
+    for field in 0..cands.len() {
+        augment(field, cands, used, &mut vec![false; used.len()], &mut owner);
+    }
+    let mut matching = vec![None; cands.len()];
+    for (col, field) in owner.iter().enumerate() {
+        if let Some(field) = field {
+            matching[*field] = Some(col);
+        }
+    }
+    matching
+}
+
+/// Lists the ways to give each field its own column, as the column of each
+/// field, stopping after `limit` of them if given. Only choices that still
+/// leave a complete matching are followed, so the search never backtracks
+/// out of a dead end.
+fn matchings(cands: &[Vec<usize>], columns: usize, limit: Option<usize>) -> Vec<Vec<usize>> {
+    fn extend(
+        cands: &[Vec<usize>],
+        limit: Option<usize>,
+        used: &mut Vec<bool>,
+        current: &mut Vec<usize>,
+        found: &mut Vec<Vec<usize>>,
+    ) {
+        let field = current.len();
+        if field == cands.len() {
+            found.push(current.clone());
+            return;
+        }
+        for &col in cands[field].iter() {
+            if used[col] || limit == Some(found.len()) {
+                continue;
+            }
+            used[col] = true;
+            current.push(col);
+            if max_matching(&cands[field + 1..], used)
+                .iter()
+                .all(|col| col.is_some())
+            {
+                extend(cands, limit, used, current, found);
+            }
+            current.pop();
+            used[col] = false;
+        }
+    }
+
+    let mut found = vec![];
+    extend(
+        cands,
+        limit,
+        &mut vec![false; columns],
+        &mut vec![],
+        &mut found,
+    );
+    found
+}
+
+impl FieldReference {
+    /// The columns whose values all fit each bound.
+    fn candidates(&self, bounds: &[Bound]) -> Vec<Vec<usize>> {
+        bounds
+            .iter()
+            .map(|bound| {
+                (0..self.fields.len())
+                    .filter(|&i| {
+                        self.fields[i]
+                            .values
+                            .iter()
+                            .all(|x| bound.valid.iter().any(|r| r.contains(x)))
                     })
-                    .collect();
-            } else {
-                break;
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Names the columns, provided the tickets allow exactly one assignment
+    /// of the fields to the columns.
+    fn assign(&mut self, r: &Reference) -> Result<(), AssignError> {
+        let cands = self.candidates(&r.bounds);
+        let columns = self.fields.len();
+        let matching = max_matching(&cands, &vec![false; columns]);
+        let unmatched: Vec<String> = matching
+            .iter()
+            .zip(r.bounds.iter())
+            .filter(|(col, _)| col.is_none())
+            .map(|(_, bound)| bound.name.clone())
+            .collect();
+        if !unmatched.is_empty() {
+            return Err(AssignError::Unmatched(unmatched));
+        }
+
+        let names = |matching: &[usize]| {
+            let mut names = vec![None; columns];
+            for (bound, &col) in r.bounds.iter().zip(matching) {
+                names[col] = Some(bound.name.clone());
             }
+            names
+        };
+        let mut found = matchings(&cands, columns, None);
+        if found.len() > 1 {
+            return Err(AssignError::Ambiguous(
+                found.iter().map(|matching| names(matching)).collect(),
+            ));
+        }
+        for (field, name) in self.fields.iter_mut().zip(names(&found.remove(0))) {
+            field.name = name;
         }
+        Ok(())
     }
 
-    fn score(&self) -> i64 {
+    /// Multiplies the values of our ticket in the fields whose name is
+    /// picked by `pick`.
+    fn score(&self, pick: impl Fn(&str) -> bool) -> i64 {
         self.fields
             .iter()
-            .filter_map(|f| {
-                if let Some(name) = &f.name {
-                    if name.starts_with("departure") {
-                        return Some(f.values[0]);
-                    }
-                }
-                None
-            })
+            .filter(|f| f.name.as_deref().is_some_and(&pick))
+            .map(|f| f.values[0])
             .product()
     }
 }
 
 fn main() {
+    let mut prefix = "departure".to_string();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prefix" => {
+                prefix = args.next().unwrap_or_else(|| {
+                    println!("--prefix needs the start of the field names to score");
+                    process::exit(1);
+                })
+            }
+            _ => {
+                println!("Unknown argument '{}'", arg);
+                process::exit(1);
+            }
+        }
+    }
+
     let reference = fs::read_to_string("input.txt")
         .unwrap()
         .parse::<Reference>()
@@ -196,8 +333,14 @@ fn main() {
     println!("The scanning error is {}", reference.scanning_error());
     let pruned_reference = reference.remove_invalids();
     let mut fieldref = FieldReference::from(&pruned_reference);
-    fieldref.assign(pruned_reference);
-    println!("The score is {}", fieldref.score());
+    if let Err(err) = fieldref.assign(&pruned_reference) {
+        println!("Failed to assign the fields: {}", err);
+        process::exit(1);
+    }
+    println!(
+        "The score is {}",
+        fieldref.score(|name| name.starts_with(&prefix))
+    );
 }
 
 #[cfg(test)]
@@ -318,9 +461,114 @@ mod test {
     fn assigned() {
         let reference = INPUT_1.parse::<Reference>().unwrap().remove_invalids();
         let mut fieldref = FieldReference::from(&reference);
-        fieldref.assign(reference);
+        assert_eq!(fieldref.assign(&reference), Ok(()));
         assert_eq!(fieldref.fields[0].name, Some("row".to_string()));
         assert_eq!(fieldref.fields[1].name, Some("class".to_string()));
         assert_eq!(fieldref.fields[2].name, Some("seat".to_string()));
     }
+
+    #[test]
+    fn matching_search() {
+        // no field has a single candidate
+        let cands = vec![vec![0, 1], vec![1, 2], vec![2, 0]];
+        assert_eq!(
+            matchings(&cands, 3, None),
+            vec![vec![0, 1, 2], vec![1, 2, 0]]
+        );
+        let cands = vec![vec![0, 1, 2], vec![0, 1], vec![1, 2], vec![3, 2]];
+        assert_eq!(
+            matchings(&cands, 4, None),
+            vec![vec![0, 1, 2, 3], vec![1, 0, 2, 3], vec![2, 0, 1, 3]]
+        );
+        assert_eq!(
+            matchings(&cands, 4, Some(2)),
+            vec![vec![0, 1, 2, 3], vec![1, 0, 2, 3]]
+        );
+        let cands = vec![vec![0, 1], vec![1, 2], vec![2, 0], vec![0]];
+        assert_eq!(matchings(&cands, 3, None), vec![] as Vec<Vec<usize>>);
+        assert_eq!(
+            max_matching(&cands, &[false; 3])
+                .iter()
+                .filter(|col| col.is_none())
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn ambiguous_and_unmatched() {
+        let reference = "
+            low: 0-5 or 8-9
+            high: 0-5 or 8-9
+            any: 0-20 or 30-40
+
+            your ticket:
+            1,2,15
+
+            nearby tickets:
+            3,4,16"
+            .parse::<Reference>()
+            .unwrap();
+        let mut fieldref = FieldReference::from(&reference);
+        let names = |names: [&str; 3]| names.iter().map(|n| Some(n.to_string())).collect();
+        assert_eq!(
+            fieldref.assign(&reference),
+            Err(AssignError::Ambiguous(vec![
+                names(["low", "high", "any"]),
+                names(["high", "low", "any"]),
+            ]))
+        );
+        assert!(fieldref.fields.iter().all(|f| f.name.is_none()));
+
+        // every assignment is listed, not just the first two
+        let reference = "
+            a: 0-5 or 8-9
+            b: 0-5 or 8-9
+            c: 0-5 or 8-9
+
+            your ticket:
+            1,2,3
+
+            nearby tickets:
+            3,4,5"
+            .parse::<Reference>()
+            .unwrap();
+        let err = FieldReference::from(&reference)
+            .assign(&reference)
+            .unwrap_err();
+        match &err {
+            AssignError::Ambiguous(assignments) => assert_eq!(assignments.len(), 6),
+            _ => panic!("expected an ambiguous assignment, got {:?}", err),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("6 assignments fit the tickets:\n  a, b, c\n"));
+
+        let reference = "
+            low: 0-5 or 8-9
+            high: 0-5 or 8-9
+            any: 0-20 or 30-40
+
+            your ticket:
+            1,12,15
+
+            nearby tickets:
+            3,14,16"
+            .parse::<Reference>()
+            .unwrap();
+        let mut fieldref = FieldReference::from(&reference);
+        assert_eq!(
+            fieldref.assign(&reference),
+            Err(AssignError::Unmatched(vec!["high".to_string()]))
+        );
+    }
+
+    #[test]
+    fn score_with_predicate() {
+        let reference = INPUT_1.parse::<Reference>().unwrap().remove_invalids();
+        let mut fieldref = FieldReference::from(&reference);
+        fieldref.assign(&reference).unwrap();
+        assert_eq!(fieldref.score(|name| name.starts_with("departure")), 1);
+        assert_eq!(fieldref.score(|name| name != "row"), 12 * 13);
+    }
 }
@@ -1,39 +1,179 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs,
+    env, fmt, fs, process,
     str::FromStr,
 };
 
-#[derive(Eq, Hash, PartialEq, Clone, Debug, Default)]
-struct Pos {
-    x: i32,
-    y: i32,
-    z: i32,
-    w: i32,
+#[derive(Eq, Hash, PartialEq, Clone, Copy, Debug)]
+struct Pos<const N: usize>([i32; N]);
+
+impl<const N: usize> Pos<N> {
+    /// The offsets to the 3^N - 1 neighbours of a cell.
+    fn offsets() -> Vec<Pos<N>> {
+        let mut offsets = vec![];
+        for i in 0..3_usize.pow(N as u32) {
+            let mut off = [0; N];
+            let mut rest = i;
+            for axis in off.iter_mut() {
+                *axis = (rest % 3) as i32 - 1;
+                rest /= 3;
+            }
+            if off != [0; N] {
+                offsets.push(Pos(off));
+            }
+        }
+        offsets
+    }
+
+    fn add(&self, off: &Pos<N>) -> Pos<N> {
+        let mut pos = self.0;
+        for (axis, d) in pos.iter_mut().zip(off.0) {
+            *axis += d;
+        }
+        Pos(pos)
+    }
 }
 
-#[derive(Debug, Default, Clone)]
-struct Core {
-    cells: HashSet<Pos>,
-    dimensions: i32,
+/// A life-like rule: an inactive cell becomes active when its number of
+/// active neighbours is in `birth`, an active cell stays active when it is in
+/// `survival`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Rule {
+    birth: Vec<u32>,
+    survival: Vec<u32>,
 }
 
-impl FromStr for Core {
+impl Rule {
+    fn conway() -> Self {
+        Rule {
+            birth: vec![3],
+            survival: vec![2, 3],
+        }
+    }
+
+    /// Fails if a count is above `neighbours`, the most a cell can have.
+    fn check(&self, neighbours: u32) -> Result<(), String> {
+        match self
+            .birth
+            .iter()
+            .chain(self.survival.iter())
+            .find(|&&count| count > neighbours)
+        {
+            Some(count) => Err(format!(
+                "{} neighbours in {}, a cell has at most {}",
+                count, self, neighbours
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn next(&self, active: bool, count: u32) -> bool {
+        if active {
+            self.survival.contains(&count)
+        } else {
+            self.birth.contains(&count)
+        }
+    }
+}
+
+impl FromStr for Rule {
     type Err = String;
+
+    /// Reads the `B3/S23` notation, one digit per neighbour count, or
+    /// comma separated counts and ranges such as `B3,12/S2-4` for the
+    /// counts above 9.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("'{}' is not a neighbour count in '{}'", n, s))
+        };
+        let counts = |part: &str| {
+            if !part.contains([',', '-']) {
+                return part
+                    .chars()
+                    .map(|c| {
+                        c.to_digit(10)
+                            .ok_or(format!("'{}' is not a neighbour count in '{}'", c, s))
+                    })
+                    .collect::<Result<Vec<u32>, String>>();
+            }
+            let mut counts = vec![];
+            for item in part.split(',') {
+                match item.split_once('-') {
+                    Some((low, high)) => counts.extend(number(low)?..=number(high)?),
+                    None => counts.push(number(item)?),
+                }
+            }
+            counts.sort_unstable();
+            counts.dedup();
+            Ok(counts)
+        };
+        let (birth, survival) = s
+            .trim()
+            .split_once('/')
+            .ok_or(format!("expected 'B<counts>/S<counts>', found '{}'", s))?;
+        match (birth.strip_prefix('B'), survival.strip_prefix('S')) {
+            (Some(birth), Some(survival)) => Ok(Rule {
+                birth: counts(birth)?,
+                survival: counts(survival)?,
+            }),
+            _ => Err(format!("expected 'B<counts>/S<counts>', found '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // single digits run together, larger counts need commas
+        let sep = if self
+            .birth
+            .iter()
+            .chain(self.survival.iter())
+            .all(|&c| c < 10)
+        {
+            ""
+        } else {
+            ","
+        };
+        let counts = |counts: &[u32]| {
+            counts
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(sep)
+        };
+        write!(f, "B{}/S{}", counts(&self.birth), counts(&self.survival))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Core<const N: usize> {
+    cells: HashSet<Pos<N>>,
+    rule: Rule,
+    /// Whether only the cells with no negative coordinate past the first
+    /// two axes are stored, the rest being their mirror images.
+    folded: bool,
+}
+
+impl<const N: usize> FromStr for Core<N> {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if N < 2 {
+            return Err(format!("a {}D core cannot hold a 2D slice", N));
+        }
         let mut core = Core {
-            cells: HashSet::<Pos>::new(),
-            dimensions: 3,
+            cells: HashSet::new(),
+            rule: Rule::conway(),
+            folded: false,
         };
-        for (y, line) in s.split("\n").enumerate() {
+        for (y, line) in s.split('\n').enumerate() {
             for (x, c) in line.chars().enumerate() {
                 if c == '#' {
-                    core.cells.insert(Pos {
-                        x: x as i32,
-                        y: y as i32,
-                        z: 0,
-                        w: 0,
-                    });
+                    let mut pos = [0; N];
+                    pos[0] = x as i32;
+                    pos[1] = y as i32;
+                    core.cells.insert(Pos(pos));
                 }
             }
         }
@@ -41,141 +181,278 @@ impl FromStr for Core {
     }
 }
 
-impl ToString for Core {
-    fn to_string(&self) -> String {
+impl<const N: usize> fmt::Display for Core<N> {
+    /// Draws every 2D slice of the bounds, headed by its coordinates along
+    /// the other axes.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.folded {
+            return write!(f, "{}", self.unfold());
+        }
         let (min, max) = self.bounds();
-        let mut s = String::default();
-
-        for w in min.w..max.w + 1 {
-            s = format!["{s}w={w}\n"];
-            for z in min.z..max.z + 1 {
-                s = format!["{s}z={z}\n"];
-                for y in min.y..max.y + 1 {
-                    for x in min.x..max.x + 1 {
-                        if self.cells.contains(&Pos { x, y, z, w }) {
-                            s += "#";
-                        } else {
-                            s += ".";
-                        }
-                    }
-                    s += "\n";
+        if self.cells.is_empty() {
+            return Ok(());
+        }
+        let mut pos = min;
+        loop {
+            let names = ["z", "w", "v", "u"];
+            let header: Vec<String> = (2..N)
+                .map(|axis| format!("{}={}", names.get(axis - 2).unwrap_or(&"?"), pos.0[axis]))
+                .collect();
+            if N > 2 {
+                writeln!(f, "{}", header.join(", "))?;
+            }
+            for y in min.0[1]..=max.0[1] {
+                for x in min.0[0]..=max.0[0] {
+                    pos.0[0] = x;
+                    pos.0[1] = y;
+                    write!(f, "{}", if self.cells.contains(&pos) { '#' } else { '.' })?;
                 }
-                s += "\n";
+                writeln!(f)?;
+            }
+            writeln!(f)?;
+            // move on to the next slice, like an odometer over the other axes
+            let Some(axis) = (2..N).find(|&axis| pos.0[axis] < max.0[axis]) else {
+                return Ok(());
+            };
+            pos.0[axis] += 1;
+            for lower in 2..axis {
+                pos.0[lower] = min.0[lower];
             }
-            s += "\n";
         }
-        s
     }
 }
 
-impl Core {
-    fn bounds(&self) -> (Pos, Pos) {
-        let mut min = Pos {
-            x: i32::MAX,
-            y: i32::MAX,
-            z: i32::MAX,
-            w: i32::MAX,
-        };
-        let mut max = Pos {
-            x: i32::MIN,
-            y: i32::MIN,
-            z: i32::MIN,
-            w: i32::MIN,
-        };
+impl<const N: usize> Core<N> {
+    /// Switches to `rule`, which must fit the 3^N - 1 neighbours of a cell.
+    fn with_rule(self, rule: &Rule) -> Result<Core<N>, String> {
+        rule.check(Pos::<N>::offsets().len() as u32)?;
+        Ok(Core {
+            rule: rule.clone(),
+            ..self
+        })
+    }
+
+    fn bounds(&self) -> (Pos<N>, Pos<N>) {
+        let mut min = Pos([i32::MAX; N]);
+        let mut max = Pos([i32::MIN; N]);
         for pos in self.cells.iter() {
-            min.x = min.x.min(pos.x);
-            min.y = min.y.min(pos.y);
-            min.z = min.z.min(pos.z);
-            min.w = min.w.min(pos.w);
-            max.x = max.x.max(pos.x);
-            max.y = max.y.max(pos.y);
-            max.z = max.z.max(pos.z);
-            max.w = max.w.max(pos.w);
+            for axis in 0..N {
+                min.0[axis] = min.0[axis].min(pos.0[axis]);
+                max.0[axis] = max.0[axis].max(pos.0[axis]);
+            }
         }
         (min, max)
     }
 
-    fn step(&self) -> Core {
-        let (min, max) = self.bounds();
-        let mut next = Core {
-            cells: HashSet::<Pos>::new(),
-            dimensions: self.dimensions,
-        };
-        let no_w = if self.dimensions == 3 {
-            0..1
+    /// The number of active neighbours of one cell, looking at each of its
+    /// 3^N - 1 neighbours in turn. Slower than `neighbour_counts` for a whole
+    /// core, which the tests check against it.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn count_neighs(&self, pos: &Pos<N>) -> u32 {
+        Pos::offsets()
+            .iter()
+            .filter(|off| self.cells.contains(&pos.add(off)))
+            .count() as u32
+    }
+
+    /// The number of active neighbours of every cell next to an active one.
+    fn neighbour_counts(&self) -> HashMap<Pos<N>, u32> {
+        let offsets = Pos::offsets();
+        let mut counts = HashMap::new();
+        for pos in self.cells.iter() {
+            for off in offsets.iter() {
+                *counts.entry(pos.add(off)).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    fn step(&self) -> Core<N> {
+        let mut counts = if self.folded {
+            self.folded_counts()
         } else {
-            (min.w - 1)..(max.w + 2)
+            self.neighbour_counts()
         };
-        for w in no_w {
-            for z in (min.z - 1)..(max.z + 2) {
-                for y in (min.y - 1)..(max.y + 2) {
-                    for x in (min.x - 1)..(max.x + 2) {
-                        let pos = Pos { x, y, z, w };
-                        let count = self.count_neighs(&pos);
-                        if !self.cells.contains(&pos) {
-                            if count == 3 {
-                                next.cells.insert(pos);
-                            }
-                        } else {
-                            if count == 2 || count == 3 {
-                                next.cells.insert(pos);
-                            }
-                        }
-                    }
+        // active cells without any active neighbour still get a say
+        for pos in self.cells.iter() {
+            counts.entry(*pos).or_insert(0);
+        }
+        Core {
+            cells: counts
+                .into_iter()
+                .filter(|(pos, count)| self.rule.next(self.cells.contains(pos), *count))
+                .map(|(pos, _)| pos)
+                .collect(),
+            rule: self.rule.clone(),
+            folded: self.folded,
+        }
+    }
+
+    /// Keeps only the non-negative half of every axis past the first two.
+    /// The core must be mirror symmetric along those axes, as any core grown
+    /// from a 2D slice is.
+    fn fold(&self) -> Core<N> {
+        Core {
+            cells: self
+                .cells
+                .iter()
+                .filter(|pos| pos.0[2..].iter().all(|&c| c >= 0))
+                .copied()
+                .collect(),
+            rule: self.rule.clone(),
+            folded: true,
+        }
+    }
+
+    /// Adds back the mirror images of a folded core.
+    fn unfold(&self) -> Core<N> {
+        let mut cells = HashSet::new();
+        for pos in self.cells.iter() {
+            let mut images = vec![*pos];
+            for axis in 2..N {
+                if pos.0[axis] != 0 {
+                    let mirrored: Vec<Pos<N>> = images
+                        .iter()
+                        .map(|image| {
+                            let mut image = *image;
+                            image.0[axis] = -image.0[axis];
+                            image
+                        })
+                        .collect();
+                    images.extend(mirrored);
                 }
             }
+            cells.extend(images);
+        }
+        Core {
+            cells,
+            rule: self.rule.clone(),
+            folded: false,
         }
-        next
-    }
-
-    fn count_neighs(&self, pos: &Pos) -> i32 {
-        let mut sum = 0;
-        let no_w = if self.dimensions == 3 { 0..1 } else { -1..2 };
-        for w in no_w {
-            for z in -1..2 {
-                for y in -1..2 {
-                    for x in -1..2 {
-                        let off = Pos {
-                            x: pos.x + x,
-                            y: pos.y + y,
-                            z: pos.z + z,
-                            w: pos.w + w,
-                        };
-                        if !(x == 0 && y == 0 && z == 0 && w == 0) && self.cells.contains(&off) {
-                            sum += 1;
-                        }
-                    }
+    }
+
+    /// The number of active neighbours, over the whole space, of every
+    /// stored cell next to an active one in a folded core.
+    ///
+    /// A neighbour at 0 along an axis where the active cell is at 1 is also
+    /// next to the mirror image of that cell at -1, so it counts twice per
+    /// such axis.
+    fn folded_counts(&self) -> HashMap<Pos<N>, u32> {
+        let offsets = Pos::offsets();
+        let mut counts = HashMap::new();
+        for pos in self.cells.iter() {
+            for off in offsets.iter() {
+                let next = pos.add(off);
+                if next.0[2..].iter().any(|&c| c < 0) {
+                    continue;
                 }
+                let mirrors = (2..N)
+                    .filter(|&axis| pos.0[axis] == 1 && next.0[axis] == 0)
+                    .count();
+                *counts.entry(next).or_insert(0) += 1 << mirrors;
             }
         }
-        sum
+        counts
     }
 
-    fn run(&self, iters: u32) -> Core {
-        println!("cycle left {}", iters);
-        println!("{}", &self.to_string());
-        if iters == 0 {
-            self.clone()
-        } else {
-            self.step().run(iters - 1)
-        }
+    fn run(&self, iters: u32) -> Core<N> {
+        (0..iters).fold(self.clone(), |core, _| core.step())
     }
 
-    fn hyper(&self) -> Core {
-        Core {
-            cells: self.cells.clone(),
-            dimensions: 4,
+    /// The number of active cells over the whole space.
+    fn count(&self) -> usize {
+        if !self.folded {
+            return self.cells.len();
         }
+        self.cells
+            .iter()
+            .map(|pos| 1 << pos.0[2..].iter().filter(|&&c| c != 0).count())
+            .sum()
+    }
+}
+
+/// Runs the slice in `N` dimensions, drawing each cycle when `show` is set.
+fn simulate<const N: usize>(
+    raw: &str,
+    rule: &Rule,
+    cycles: u32,
+    show: bool,
+    fold: bool,
+) -> Result<usize, String> {
+    let mut core = raw.parse::<Core<N>>()?.with_rule(rule)?;
+    if fold {
+        core = core.fold();
+    }
+    if !show {
+        return Ok(core.run(cycles).count());
+    }
+    for cycle in 0..cycles {
+        println!("cycle {} ({} active)\n{}", cycle, core.count(), core);
+        core = core.step();
     }
+    println!("cycle {} ({} active)\n{}", cycles, core.count(), core);
+    Ok(core.count())
 }
 
 fn main() {
-    let core: Core = fs::read_to_string("input.txt").unwrap().parse().unwrap();
-    println!("Core after 6 steps: {}", core.run(6).cells.len());
-    println!(
-        "Hypercore after 6 steps: {}",
-        core.hyper().run(6).cells.len()
-    );
+    let mut dims = vec![];
+    let mut rule = Rule::conway();
+    let mut cycles = 6;
+    let mut show = false;
+    let mut fold = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--show" => show = true,
+            "--fold" => fold = true,
+            "--dims" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) if (2..=6).contains(&n) => dims.push(n),
+                _ => {
+                    println!("--dims needs a dimension between 2 and 6");
+                    process::exit(1);
+                }
+            },
+            "--cycles" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) => cycles = n,
+                _ => {
+                    println!("--cycles needs a number of cycles");
+                    process::exit(1);
+                }
+            },
+            "--rule" => {
+                rule = args
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .unwrap_or_else(|err| {
+                        println!("Bad rule: {}", err);
+                        process::exit(1);
+                    })
+            }
+            _ => {
+                println!("Unknown argument '{}'", arg);
+                process::exit(1);
+            }
+        }
+    }
+    if dims.is_empty() {
+        dims = vec![3, 4];
+    }
+
+    let raw = fs::read_to_string("input.txt").unwrap();
+    for n in dims {
+        let count = match n {
+            2 => simulate::<2>(&raw, &rule, cycles, show, fold),
+            3 => simulate::<3>(&raw, &rule, cycles, show, fold),
+            4 => simulate::<4>(&raw, &rule, cycles, show, fold),
+            5 => simulate::<5>(&raw, &rule, cycles, show, fold),
+            _ => simulate::<6>(&raw, &rule, cycles, show, fold),
+        };
+        match count {
+            Ok(count) => println!("{}D core after {} cycles of {}: {}", n, cycles, rule, count),
+            Err(err) => println!("Cannot run the {}D core: {}", n, err),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -186,20 +463,114 @@ mod test {
 
     #[test]
     fn parse() {
-        let core: Core = INPUT.parse().unwrap();
+        let core: Core<3> = INPUT.parse().unwrap();
         assert_eq!(core.cells.len(), 5);
+        assert!(INPUT.parse::<Core<1>>().is_err());
     }
 
     #[test]
     fn run() {
-        let core: Core = INPUT.parse().unwrap();
+        let core: Core<3> = INPUT.parse().unwrap();
         assert_eq!(core.run(1).cells.len(), 11);
         assert_eq!(core.run(6).cells.len(), 112);
     }
 
     #[test]
     fn hyperrun() {
-        let core: Core = INPUT.parse().unwrap();
-        assert_eq!(core.hyper().run(6).cells.len(), 848);
+        let core: Core<4> = INPUT.parse().unwrap();
+        assert_eq!(core.run(6).cells.len(), 848);
+    }
+
+    #[test]
+    fn flat_life() {
+        // a glider moves one cell diagonally every 4 generations
+        let core: Core<2> = INPUT.parse().unwrap();
+        let moved: HashSet<Pos<2>> = core.cells.iter().map(|p| p.add(&Pos([1, 1]))).collect();
+        assert_eq!(core.run(4).cells, moved);
+        assert_eq!(core.to_string(), ".#.\n..#\n###\n\n");
+    }
+
+    #[test]
+    fn counts_in_any_dimension() {
+        assert_eq!(Pos::<2>::offsets().len(), 8);
+        assert_eq!(Pos::<5>::offsets().len(), 242);
+        let core: Core<5> = INPUT.parse().unwrap();
+        let counts = core.neighbour_counts();
+        for (pos, count) in counts.iter() {
+            assert_eq!(core.count_neighs(pos), *count);
+        }
+        assert_eq!(core.count_neighs(&Pos([1, 1, 0, 0, 0])), 5);
+        assert_eq!(core.count_neighs(&Pos([1, 1, 1, -1, 0])), 5);
+        assert_eq!(core.count_neighs(&Pos([9, 9, 9, 9, 9])), 0);
+        let (min, max) = core.run(1).bounds();
+        assert_eq!((min, max), (Pos([0, 1, -1, -1, -1]), Pos([2, 3, 1, 1, 1])));
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!("B3/S23".parse(), Ok(Rule::conway()));
+        assert_eq!(Rule::conway().to_string(), "B3/S23");
+        let rule: Rule = "B36/S".parse().unwrap();
+        assert_eq!(rule.survival, vec![]);
+        assert!("S23/B3".parse::<Rule>().is_err());
+        assert!("B3x/S23".parse::<Rule>().is_err());
+        assert!("B3,x/S23".parse::<Rule>().is_err());
+
+        // counts above 9 are written with commas or ranges
+        let rule: Rule = "B3,12/S2-4,26".parse().unwrap();
+        assert_eq!(rule.birth, vec![3, 12]);
+        assert_eq!(rule.survival, vec![2, 3, 4, 26]);
+        assert_eq!(rule.to_string(), "B3,12/S2,3,4,26");
+        assert_eq!(rule.to_string().parse(), Ok(rule.clone()));
+        assert_eq!(
+            "B3,/S2,3".parse::<Rule>(),
+            Err("'' is not a neighbour count in 'B3,/S2,3'".to_string())
+        );
+
+        // no cell has more than 3^N - 1 neighbours
+        let core: Core<3> = INPUT.parse().unwrap();
+        assert!(core.clone().with_rule(&rule).is_ok());
+        let rule: Rule = "B3/S2,27".parse().unwrap();
+        assert_eq!(
+            core.clone().with_rule(&rule).unwrap_err(),
+            "27 neighbours in B3/S2,27, a cell has at most 26"
+        );
+        assert!(simulate::<2>(INPUT, &"B9/S".parse().unwrap(), 1, false, false).is_err());
+        assert_eq!(
+            simulate::<3>(INPUT, &Rule::conway(), 6, false, true),
+            Ok(112)
+        );
+
+        // with no survivors only the newborn cells are left
+        let mut core: Core<3> = INPUT.parse().unwrap();
+        core.rule = "B3/S".parse().unwrap();
+        let next = core.run(1);
+        assert_eq!(next.count(), 8);
+        assert!(next.cells.is_disjoint(&core.cells));
+    }
+
+    fn assert_folds<const N: usize>(raw: &str, rule: &str, cycles: u32) {
+        let mut core: Core<N> = raw.parse().unwrap();
+        core.rule = rule.parse().unwrap();
+        let mut folded = core.fold();
+        for _ in 0..cycles {
+            core = core.step();
+            folded = folded.step();
+            assert_eq!(folded.count(), core.count());
+            assert_eq!(folded.unfold().cells, core.cells);
+        }
+    }
+
+    #[test]
+    fn folded_matches_full() {
+        let raw = fs::read_to_string("input.txt").unwrap();
+        for rule in ["B3/S23", "B36/S23", "B2/S", "B35678/S5678"] {
+            assert_folds::<3>(INPUT, rule, 6);
+            assert_folds::<4>(INPUT, rule, 6);
+            assert_folds::<3>(&raw, rule, 4);
+            assert_folds::<4>(&raw, rule, 3);
+        }
+        let core: Core<4> = INPUT.parse().unwrap();
+        assert_eq!(core.fold().run(6).count(), 848);
     }
 }
@@ -1,9 +1,27 @@
-use std::fs;
+use std::{env, fmt, fs, process};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Operator {
     Sum,
+    Sub,
     Mul,
+    Div,
+    Pow,
+    /// Unary minus, lexed as `Sub` and told apart by the parser.
+    Neg,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Sum => "+",
+            Operator::Sub | Operator::Neg => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Pow => "^",
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -19,31 +37,113 @@ enum Token {
     Flow(Parenthesis),
 }
 
-fn lexer(s: &str) -> Vec<Token> {
-    let mut tokens = vec![];
-    for c in s.replace(" ", "").chars() {
-        match c {
-            '+' => tokens.push(Token::Op(Operator::Sum)),
-            '*' => tokens.push(Token::Op(Operator::Mul)),
-            '(' => tokens.push(Token::Flow(Parenthesis::Open)),
-            ')' => tokens.push(Token::Flow(Parenthesis::Close)),
-            num => tokens.push(Token::Term(num.to_string().parse().unwrap())),
+/// The columns `start..end` of a line, counting from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+impl Span {
+    fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    fn to(&self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Error {
+    span: Span,
+    msg: String,
+}
+
+impl Error {
+    fn new(span: Span, msg: impl Into<String>) -> Self {
+        Error {
+            span,
+            msg: msg.into(),
         }
     }
-    tokens
+
+    /// Underlines the span of the error in the line it comes from.
+    fn show(&self, line: &str) -> String {
+        let width = (self.span.end - self.span.start).max(1);
+        format!(
+            "{}\n{}{} {}",
+            line,
+            " ".repeat(self.span.start),
+            "^".repeat(width),
+            self.msg
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "columns {}-{}: {}",
+            self.span.start + 1,
+            self.span.end,
+            self.msg
+        )
+    }
+}
+
+fn lexer(s: &str) -> Result<Vec<(Token, Span)>, Error> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = s.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let token = match chars[i] {
+            ' ' | '\t' => {
+                i += 1;
+                continue;
+            }
+            '+' => Token::Op(Operator::Sum),
+            '-' => Token::Op(Operator::Sub),
+            '*' => Token::Op(Operator::Mul),
+            '/' => Token::Op(Operator::Div),
+            '^' => Token::Op(Operator::Pow),
+            '(' => Token::Flow(Parenthesis::Open),
+            ')' => Token::Flow(Parenthesis::Close),
+            '0'..='9' => {
+                let len = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let span = Span::new(i, i + len);
+                let digits: String = chars[i..i + len].iter().collect();
+                let num = digits
+                    .parse()
+                    .map_err(|_| Error::new(span, "number too large"))?;
+                tokens.push((Token::Term(num), span));
+                i += len;
+                continue;
+            }
+            c => return Err(Error::new(Span::new(i, i + 1), format!("unknown '{}'", c))),
+        };
+        tokens.push((token, Span::new(i, i + 1)));
+        i += 1;
+    }
+    Ok(tokens)
 }
 
-trait Node {
+trait Node: Sized {
     fn value(&self) -> Option<i64>;
-    fn op(&self, rhs: Self, lhs: Self) -> Self;
+    fn operator(&self) -> Option<Operator>;
     fn flow(&self) -> Option<Parenthesis>;
-    fn priority(&self) -> Option<Operator>;
+    /// The node standing for `op` once it has been read.
+    fn from_operator(op: Operator) -> Self;
+    fn apply(&self, operands: &[i64]) -> Result<i64, String>;
 }
 
+/// An expression: a number, or an operator applied to its operands.
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Tree<T: Node> {
-    nodes: Vec<Box<Tree<T>>>,
-    value: Option<T>,
+    nodes: Vec<Tree<T>>,
+    value: T,
+    span: Span,
 }
 
 impl Node for Token {
@@ -53,125 +153,423 @@ impl Node for Token {
             _ => None,
         }
     }
-    fn op(&self, lhs: Self, rhs: Self) -> Self {
-        let v = lhs.value().unwrap();
-        let w = rhs.value().unwrap();
+
+    fn operator(&self) -> Option<Operator> {
         match self {
-            Token::Op(Operator::Sum) => Token::Term(v + w),
-            Token::Op(Operator::Mul) => Token::Term(v * w),
-            _ => self.clone(),
+            Token::Op(op) => Some(*op),
+            _ => None,
         }
     }
+
     fn flow(&self) -> Option<Parenthesis> {
         match self {
             Token::Flow(paren) => Some(paren.clone()),
             _ => None,
         }
     }
-    fn priority(&self) -> Option<Operator> {
-        match self {
-            Token::Op(op) => Some(op.clone()),
-            _ => None,
+
+    fn from_operator(op: Operator) -> Self {
+        Token::Op(op)
+    }
+
+    fn apply(&self, operands: &[i64]) -> Result<i64, String> {
+        let overflow = || "overflow".to_string();
+        match (self, operands) {
+            (Token::Term(num), []) => Ok(*num),
+            (Token::Op(Operator::Neg), &[v]) => v.checked_neg().ok_or_else(overflow),
+            (Token::Op(op), &[v, w]) => match op {
+                Operator::Sum => v.checked_add(w).ok_or_else(overflow),
+                Operator::Sub => v.checked_sub(w).ok_or_else(overflow),
+                Operator::Mul => v.checked_mul(w).ok_or_else(overflow),
+                Operator::Div if w == 0 => Err("division by zero".to_string()),
+                Operator::Div => v.checked_div(w).ok_or_else(overflow),
+                Operator::Pow => {
+                    let exp = u32::try_from(w).map_err(|_| "negative exponent".to_string())?;
+                    v.checked_pow(exp).ok_or_else(overflow)
+                }
+                Operator::Neg => Err("unary minus takes one operand".to_string()),
+            },
+            _ => Err(format!(
+                "{:?} cannot take {} operands",
+                self,
+                operands.len()
+            )),
         }
     }
 }
 
-fn parse_rec<T>(tokens: Vec<T>) -> (Tree<T>, Vec<T>)
-where
-    T: Clone,
-    T: Node,
-    T: std::fmt::Debug,
-{
-    let mut tree = Tree::<T> {
-        value: None,
-        nodes: vec![],
-    };
-    let mut parsed = tokens.clone();
-    while parsed.len() > 0 {
-        if Some(Parenthesis::Close) == parsed[0].flow() {
-            parsed.remove(0);
-            break;
-        } else if Some(Parenthesis::Open) == parsed[0].flow() {
-            let (subtree, remaining) = parse_rec::<T>(parsed.iter().skip(1).cloned().collect());
-            tree.nodes.push(Box::new(subtree));
-            parsed = remaining;
-        } else {
-            tree.nodes.push(Box::new(Tree::<T> {
-                value: Some(parsed[0].clone()),
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Assoc {
+    Left,
+    Right,
+}
+
+/// How tightly each operator binds, higher first. `Neg` gives the binding of
+/// unary minus, which is always read as a prefix.
+#[derive(Clone, Debug)]
+struct Table {
+    ops: Vec<(Operator, u32, Assoc)>,
+}
+
+impl Table {
+    /// Every binary operator but `^` at the same level, read left to right.
+    fn flat() -> Self {
+        Table {
+            ops: vec![
+                (Operator::Sum, 1, Assoc::Left),
+                (Operator::Sub, 1, Assoc::Left),
+                (Operator::Mul, 1, Assoc::Left),
+                (Operator::Div, 1, Assoc::Left),
+                (Operator::Neg, 2, Assoc::Right),
+                (Operator::Pow, 3, Assoc::Right),
+            ],
+        }
+    }
+
+    /// Addition and subtraction before multiplication and division.
+    fn addition_first() -> Self {
+        Table {
+            ops: vec![
+                (Operator::Mul, 1, Assoc::Left),
+                (Operator::Div, 1, Assoc::Left),
+                (Operator::Sum, 2, Assoc::Left),
+                (Operator::Sub, 2, Assoc::Left),
+                (Operator::Neg, 3, Assoc::Right),
+                (Operator::Pow, 4, Assoc::Right),
+            ],
+        }
+    }
+
+    /// The usual order of operations, where `-2^2` is `-4`.
+    fn standard() -> Self {
+        Table {
+            ops: vec![
+                (Operator::Sum, 1, Assoc::Left),
+                (Operator::Sub, 1, Assoc::Left),
+                (Operator::Mul, 2, Assoc::Left),
+                (Operator::Div, 2, Assoc::Left),
+                (Operator::Neg, 3, Assoc::Right),
+                (Operator::Pow, 4, Assoc::Right),
+            ],
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "flat" => Ok(Table::flat()),
+            "addition" => Ok(Table::addition_first()),
+            "standard" => Ok(Table::standard()),
+            _ => Err(format!(
+                "unknown table '{}', expected flat, addition or standard",
+                name
+            )),
+        }
+    }
+
+    fn get(&self, op: Operator) -> Option<(u32, Assoc)> {
+        self.ops
+            .iter()
+            .find(|(o, _, _)| *o == op)
+            .map(|&(_, prec, assoc)| (prec, assoc))
+    }
+}
+
+/// A precedence climbing parser over a line of tokens.
+struct Parser<'a, T: Node> {
+    tokens: Vec<(T, Span)>,
+    pos: usize,
+    /// Where the line ends, for errors about missing tokens.
+    end: usize,
+    table: &'a Table,
+}
+
+impl<T: Node + Clone> Parser<'_, T> {
+    fn peek(&self) -> Option<&(T, Span)> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<(T, Span), Error> {
+        let token =
+            self.tokens.get(self.pos).cloned().ok_or_else(|| {
+                Error::new(Span::new(self.end, self.end + 1), "expected a number")
+            })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// Reads a number, a parenthesised expression or a prefixed operand.
+    fn operand(&mut self) -> Result<Tree<T>, Error> {
+        let (token, span) = self.next()?;
+        if token.value().is_some() {
+            return Ok(Tree {
                 nodes: vec![],
-            }));
-            parsed.remove(0);
+                value: token,
+                span,
+            });
+        }
+        match (token.flow(), token.operator()) {
+            (Some(Parenthesis::Open), _) => {
+                let mut inner = self.expression(0)?;
+                match self.peek() {
+                    Some((close, close_span)) if close.flow() == Some(Parenthesis::Close) => {
+                        inner.span = span.to(*close_span);
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    _ => Err(Error::new(span, "unclosed parenthesis")),
+                }
+            }
+            (_, Some(Operator::Sub)) => {
+                let (prec, _) = self
+                    .table
+                    .get(Operator::Neg)
+                    .ok_or_else(|| Error::new(span, "unary minus is not in the table"))?;
+                let operand = self.expression(prec)?;
+                Ok(Tree {
+                    span: span.to(operand.span),
+                    nodes: vec![operand],
+                    value: T::from_operator(Operator::Neg),
+                })
+            }
+            (Some(Parenthesis::Close), _) => Err(Error::new(span, "unmatched ')'")),
+            _ => Err(Error::new(span, "expected a number")),
+        }
+    }
+
+    /// Reads operands joined by operators binding at least as tight as
+    /// `min_prec`.
+    fn expression(&mut self, min_prec: u32) -> Result<Tree<T>, Error> {
+        let mut lhs = self.operand()?;
+        while let Some((token, span)) = self.peek() {
+            if token.flow() == Some(Parenthesis::Close) {
+                break;
+            }
+            let op = token
+                .operator()
+                .ok_or_else(|| Error::new(*span, "expected an operator"))?;
+            let (prec, assoc) = self
+                .table
+                .get(op)
+                .filter(|_| op != Operator::Neg)
+                .ok_or_else(|| Error::new(*span, "not a binary operator in the table"))?;
+            if prec < min_prec {
+                break;
+            }
+            let (op, _) = self.next()?;
+            let rhs = self.expression(match assoc {
+                Assoc::Left => prec + 1,
+                Assoc::Right => prec,
+            })?;
+            lhs = Tree {
+                span: lhs.span.to(rhs.span),
+                nodes: vec![lhs, rhs],
+                value: op,
+            };
         }
+        Ok(lhs)
     }
-    (tree, parsed)
 }
 
-fn parser<T>(tokens: Vec<T>) -> Tree<T>
+fn parser<T>(tokens: Vec<(T, Span)>, table: &Table) -> Result<Tree<T>, Error>
 where
     T: Clone,
     T: Node,
-    T: std::fmt::Debug,
 {
-    parse_rec(tokens).0
+    let end = tokens.last().map_or(0, |(_, span)| span.end);
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end,
+        table,
+    };
+    let tree = parser.expression(0)?;
+    match parser.peek() {
+        Some((_, span)) => Err(Error::new(*span, "unmatched ')'")),
+        None => Ok(tree),
+    }
 }
 
-fn exec<T>(tree: Tree<T>, precedence: &Option<Operator>) -> Tree<T>
-where
-    T: Clone,
-    T: Node,
-    T: std::fmt::Debug,
-{
-    if tree.value.is_some() {
-        tree
-    } else {
-        let mut prec = precedence.clone();
-        let mut parsed = tree.nodes.clone();
-        let mut i: usize = 1;
-        loop {
-            if i >= parsed.len() {
-                if parsed.len() == 1 {
-                    break *parsed[0].clone();
-                } else {
-                    i = 1;
-                    prec = None;
-                }
-            }
-            if prec.is_none() || prec == parsed[i].clone().value.unwrap().priority() {
-                let prevvalue = exec(*parsed[i - 1].clone(), precedence).value.unwrap();
-                let opvalue = parsed[i].clone().value.unwrap();
-                let nextvalue = exec(*parsed[i + 1].clone(), precedence).value.unwrap();
-                parsed[i] = Box::new(Tree::<T> {
-                    value: Some(opvalue.op(prevvalue, nextvalue)),
-                    nodes: vec![],
-                });
-                parsed.remove(i + 1);
-                parsed.remove(i - 1);
+fn exec<T: Node>(tree: &Tree<T>) -> Result<i64, Error> {
+    let operands = tree.nodes.iter().map(exec).collect::<Result<Vec<_>, _>>()?;
+    tree.value
+        .apply(&operands)
+        .map_err(|msg| Error::new(tree.span, msg))
+}
+
+fn evaluate(line: &str, table: &Table) -> Result<i64, Error> {
+    exec(&parser(lexer(line)?, table)?)
+}
+
+impl<T: Node> Tree<T> {
+    /// The operator of the node, unless it is a number.
+    fn operator(&self) -> Option<Operator> {
+        self.value.operator()
+    }
+
+    /// Writes the expression back with only the parentheses that `table`
+    /// needs to read it as this tree.
+    fn print(&self, table: &Table) -> String {
+        let mut out = String::new();
+        self.print_into(table, None, &mut out);
+        out
+    }
+
+    /// Writes the node given the precedence of the operator right after it,
+    /// which a trailing unary minus would otherwise swallow.
+    fn print_into(&self, table: &Table, next: Option<u32>, out: &mut String) {
+        let Some(op) = self.operator() else {
+            out.push_str(&self.value.value().unwrap_or_default().to_string());
+            return;
+        };
+        let binding = |op| table.get(op).unwrap_or((0, Assoc::Left));
+        let (prec, assoc) = binding(op);
+        // prints a child, wrapped when the parser would otherwise split the
+        // text at another place
+        let child = |tree: &Tree<T>, wrap: bool, next, out: &mut String| {
+            if wrap {
+                out.push('(');
+                tree.print_into(table, None, out);
+                out.push(')');
             } else {
-                i += 2;
+                tree.print_into(table, next, out);
+            }
+        };
+        let binds_looser = |tree: &Tree<T>, right: bool| match tree.operator() {
+            Some(Operator::Neg) | None => false,
+            Some(child) => {
+                let (child_prec, child_assoc) = binding(child);
+                child_prec < prec
+                    || child_prec == prec
+                        && (child_assoc != assoc || right == (assoc == Assoc::Left))
+            }
+        };
+        // a leading unary minus takes in every operator binding at least as
+        // tight as itself
+        let swallows = |tree: &Tree<T>, next: Option<u32>| {
+            tree.operator() == Some(Operator::Neg)
+                && next.is_some_and(|next| next >= binding(Operator::Neg).0)
+        };
+        match self.nodes.as_slice() {
+            [operand] => {
+                out.push_str(&op.to_string());
+                child(operand, binds_looser(operand, true), next, out);
             }
+            [lhs, rhs] => {
+                let wrap = binds_looser(lhs, false) || swallows(lhs, Some(prec));
+                child(lhs, wrap, Some(prec), out);
+                out.push_str(&format!(" {} ", op));
+                let wrap = binds_looser(rhs, true) || swallows(rhs, next);
+                child(rhs, wrap, next, out);
+            }
+            _ => out.push_str(&format!("{}?", op)),
+        }
+    }
+
+    /// The tree as an S-expression, unary minus written `neg`.
+    fn sexp(&self) -> String {
+        match self.operator() {
+            None => self.value.value().unwrap_or_default().to_string(),
+            Some(op) => {
+                let name = match op {
+                    Operator::Neg => "neg".to_string(),
+                    _ => op.to_string(),
+                };
+                let nodes: Vec<String> = self.nodes.iter().map(|node| node.sexp()).collect();
+                format!("({} {})", name, nodes.join(" "))
+            }
+        }
+    }
+
+    /// The tree one node per line, children indented under their operator.
+    fn outline(&self) -> String {
+        let mut lines = vec![];
+        self.outline_into(0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn outline_into(&self, depth: usize, lines: &mut Vec<String>) {
+        let label = match self.operator() {
+            None => self.value.value().unwrap_or_default().to_string(),
+            Some(Operator::Neg) => "neg".to_string(),
+            Some(op) => op.to_string(),
+        };
+        lines.push(format!("{}{}", "  ".repeat(depth), label));
+        for node in self.nodes.iter() {
+            node.outline_into(depth + 1, lines);
         }
     }
 }
 
 fn main() {
+    let mut table = Table::standard();
+    let mut rewrite = None;
+    let mut sexp = false;
+    let mut outline = false;
+    let mut exprs = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--table" | "--as" => {
+                let named =
+                    Table::from_name(&args.next().unwrap_or_default()).unwrap_or_else(|err| {
+                        println!("{}", err);
+                        process::exit(1);
+                    });
+                if arg == "--table" {
+                    table = named;
+                } else {
+                    rewrite = Some(named);
+                }
+            }
+            "--sexp" => sexp = true,
+            "--tree" => outline = true,
+            _ => exprs.push(arg),
+        }
+    }
+    if !exprs.is_empty() {
+        for expr in exprs {
+            let tree = match lexer(&expr).and_then(|tokens| parser(tokens, &table)) {
+                Ok(tree) => tree,
+                Err(err) => {
+                    println!("{}", err.show(&expr));
+                    continue;
+                }
+            };
+            match exec(&tree) {
+                Ok(value) => println!("{} = {}", tree.print(&table), value),
+                Err(err) => println!("{}", err.show(&expr)),
+            }
+            if let Some(rewrite) = &rewrite {
+                println!("  rewritten: {}", tree.print(rewrite));
+            }
+            if sexp {
+                println!("  {}", tree.sexp());
+            }
+            if outline {
+                println!("{}", tree.outline());
+            }
+        }
+        return;
+    }
+
     let input = fs::read_to_string("input.txt").unwrap();
-    let summer = |op| {
+    let summer = |table: &Table| {
         input
-            .split("\n")
-            .filter(|line| line.len() > 0)
-            .map(|line| {
-                exec(parser(lexer(line)), &op)
-                    .value
-                    .unwrap()
-                    .value()
-                    .unwrap()
+            .split('\n')
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(i, line)| {
+                evaluate(line, table).unwrap_or_else(|err| {
+                    println!("Failed to evaluate line {}:\n{}", i + 1, err.show(line));
+                    process::exit(1);
+                })
             })
             .sum::<i64>()
     };
-    println!("The sum of all the lines is {}", summer(None));
+    println!("The sum of all the lines is {}", summer(&Table::flat()));
     println!(
         "The sum of all the lines with + over * is {}",
-        summer(Some(Operator::Sum))
+        summer(&Table::addition_first())
     );
 }
 
@@ -184,10 +582,19 @@ mod test {
     const TEST_2: &str = "5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))";
     const TEST_3: &str = "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2";
 
+    fn leaf(num: i64, start: usize) -> Tree<Token> {
+        Tree {
+            nodes: vec![],
+            value: Token::Term(num),
+            span: Span::new(start, start + 1),
+        }
+    }
+
     #[test]
     fn lexer_gen() {
+        let tokens: Vec<Token> = lexer(TEST_0).unwrap().into_iter().map(|(t, _)| t).collect();
         assert_eq!(
-            lexer(TEST_0),
+            tokens,
             vec![
                 Token::Term(2),
                 Token::Op(Operator::Mul),
@@ -200,114 +607,199 @@ mod test {
                 Token::Flow(Parenthesis::Close),
             ]
         );
+        assert_eq!(
+            lexer("12 - 345").unwrap(),
+            vec![
+                (Token::Term(12), Span::new(0, 2)),
+                (Token::Op(Operator::Sub), Span::new(3, 4)),
+                (Token::Term(345), Span::new(5, 8)),
+            ]
+        );
     }
 
     #[test]
     fn tree_gen() {
         assert_eq!(
-            parser(lexer(TEST_0)),
-            Tree::<Token> {
-                value: None,
+            parser(lexer(TEST_0).unwrap(), &Table::flat()),
+            Ok(Tree {
+                value: Token::Op(Operator::Sum),
+                span: Span::new(0, 15),
                 nodes: vec![
-                    Box::new(Tree::<Token> {
-                        value: Some(Token::Term(2)),
-                        nodes: vec![],
-                    }),
-                    Box::new(Tree::<Token> {
-                        value: Some(Token::Op(Operator::Mul)),
-                        nodes: vec![],
-                    }),
-                    Box::new(Tree::<Token> {
-                        value: Some(Token::Term(3)),
-                        nodes: vec![],
-                    }),
-                    Box::new(Tree::<Token> {
-                        value: Some(Token::Op(Operator::Sum)),
-                        nodes: vec![],
-                    }),
-                    Box::new(Tree::<Token> {
-                        value: None,
-                        nodes: vec![
-                            Box::new(Tree::<Token> {
-                                value: Some(Token::Term(4)),
-                                nodes: vec![],
-                            }),
-                            Box::new(Tree::<Token> {
-                                value: Some(Token::Op(Operator::Mul)),
-                                nodes: vec![],
-                            }),
-                            Box::new(Tree::<Token> {
-                                value: Some(Token::Term(5)),
-                                nodes: vec![],
-                            }),
-                        ],
-                    }),
+                    Tree {
+                        value: Token::Op(Operator::Mul),
+                        span: Span::new(0, 5),
+                        nodes: vec![leaf(2, 0), leaf(3, 4)],
+                    },
+                    Tree {
+                        value: Token::Op(Operator::Mul),
+                        span: Span::new(8, 15),
+                        nodes: vec![leaf(4, 9), leaf(5, 13)],
+                    },
                 ]
-            }
+            })
         );
     }
 
     #[test]
     fn exec_test() {
+        let eval = |line| evaluate(line, &Table::flat());
+        assert_eq!(eval(TEST_0), Ok(26));
+        assert_eq!(eval(TEST_1), Ok(437));
+        assert_eq!(eval(TEST_2), Ok(12240));
+        assert_eq!(eval(TEST_3), Ok(13632));
+    }
+
+    #[test]
+    fn exec_test_precedence() {
+        let eval = |line| evaluate(line, &Table::addition_first());
+        assert_eq!(eval(TEST_0), Ok(46));
+        assert_eq!(eval(TEST_1), Ok(1445));
+        assert_eq!(eval(TEST_2), Ok(669060));
+        assert_eq!(eval(TEST_3), Ok(23340));
+    }
+
+    #[test]
+    fn full_operator_set() {
+        let eval = |line| evaluate(line, &Table::standard());
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), Ok(5));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("64 / 4 / 2"), Ok(8));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Ok(512));
+        assert_eq!(eval("-2 ^ 2"), Ok(-4));
+        assert_eq!(eval("(-2) ^ 2"), Ok(4));
+        assert_eq!(eval("3 * -(2 + 1) - -1"), Ok(-8));
+        assert_eq!(eval("7 / -2"), Ok(-3));
+        assert_eq!(evaluate("-2 ^ 2", &Table::flat()), Ok(-4));
+        assert_eq!(evaluate("2 * -3 + 4", &Table::flat()), Ok(-2));
+        assert_eq!(evaluate("2 * -3 + 4", &Table::addition_first()), Ok(2));
+    }
+
+    #[test]
+    fn error_spans() {
+        let err = |line| evaluate(line, &Table::standard()).unwrap_err();
+        assert_eq!(err("1 + a"), Error::new(Span::new(4, 5), "unknown 'a'"));
         assert_eq!(
-            exec(parser(lexer(TEST_0)), &None),
-            Tree::<Token> {
-                value: Some(Token::Term(26)),
-                nodes: vec![]
-            }
-        );
-        assert_eq!(
-            exec(parser(lexer(TEST_1)), &None),
-            Tree::<Token> {
-                value: Some(Token::Term(437)),
-                nodes: vec![]
-            }
+            err("(1 + 2"),
+            Error::new(Span::new(0, 1), "unclosed parenthesis")
         );
+        assert_eq!(err("1 + 2)"), Error::new(Span::new(5, 6), "unmatched ')'"));
+        assert_eq!(err(")"), Error::new(Span::new(0, 1), "unmatched ')'"));
+        assert_eq!(err("1 +"), Error::new(Span::new(3, 4), "expected a number"));
         assert_eq!(
-            exec(parser(lexer(TEST_2)), &None),
-            Tree::<Token> {
-                value: Some(Token::Term(12240)),
-                nodes: vec![]
-            }
+            err("1 2"),
+            Error::new(Span::new(2, 3), "expected an operator")
         );
+        assert_eq!(err("()").span, Span::new(1, 2));
         assert_eq!(
-            exec(parser(lexer(TEST_3)), &None),
-            Tree::<Token> {
-                value: Some(Token::Term(13632)),
-                nodes: vec![]
-            }
+            err("2 * (3 / (1 - 1))"),
+            Error::new(Span::new(4, 17), "division by zero")
         );
+        assert_eq!(err("1 + 2 ^ 70"), Error::new(Span::new(4, 10), "overflow"));
+        assert_eq!(err("2 ^ -1").msg, "negative exponent");
+        assert_eq!(err("99999999999999999999").msg, "number too large");
+        assert_eq!(err("1 + a").show("1 + a"), "1 + a\n    ^ unknown 'a'");
+    }
+
+    fn reparse(tree: &Tree<Token>, table: &Table) -> Tree<Token> {
+        let printed = tree.print(table);
+        parser(lexer(&printed).unwrap(), table).unwrap()
     }
 
     #[test]
-    fn exec_test_precedence() {
+    fn minimal_parentheses() {
+        let tables = [Table::flat(), Table::addition_first(), Table::standard()];
+        let tree = parser(lexer(TEST_0).unwrap(), &Table::flat()).unwrap();
+        assert_eq!(tree.print(&Table::flat()), TEST_0);
+        assert_eq!(tree.print(&Table::standard()), "2 * 3 + 4 * 5");
+        assert_eq!(tree.print(&Table::addition_first()), "(2 * 3) + (4 * 5)");
+
+        let print =
+            |line, from: &Table, to: &Table| parser(lexer(line).unwrap(), from).unwrap().print(to);
+        let standard = Table::standard();
         assert_eq!(
-            exec(parser(lexer(TEST_0)), &Some(Operator::Sum)),
-            Tree::<Token> {
-                value: Some(Token::Term(46)),
-                nodes: vec![]
-            }
+            print("((1 - 2) - (3 - 4))", &standard, &standard),
+            "1 - 2 - (3 - 4)"
         );
         assert_eq!(
-            exec(parser(lexer(TEST_1)), &Some(Operator::Sum)),
-            Tree::<Token> {
-                value: Some(Token::Term(1445)),
-                nodes: vec![]
-            }
+            print("(2 ^ 3) ^ (4 ^ 5)", &standard, &standard),
+            "(2 ^ 3) ^ 4 ^ 5"
         );
         assert_eq!(
-            exec(parser(lexer(TEST_2)), &Some(Operator::Sum)),
-            Tree::<Token> {
-                value: Some(Token::Term(669060)),
-                nodes: vec![]
-            }
+            print("(-2) ^ 2 * -(3 + 4)", &standard, &standard),
+            "(-2) ^ 2 * -(3 + 4)"
         );
-        assert_eq!(
-            exec(parser(lexer(TEST_3)), &Some(Operator::Sum)),
-            Tree::<Token> {
-                value: Some(Token::Term(23340)),
-                nodes: vec![]
+        assert_eq!(print("--(2 ^ 2)", &standard, &standard), "--2 ^ 2");
+        assert_eq!(print("1 + 2 * 3", &standard, &Table::flat()), "1 + (2 * 3)");
+
+        for table in tables.iter() {
+            for line in [TEST_0, TEST_1, TEST_2, TEST_3] {
+                let tree = parser(lexer(line).unwrap(), table).unwrap();
+                for to in tables.iter() {
+                    let printed = tree.print(to);
+                    assert_eq!(reparse(&tree, to).sexp(), tree.sexp(), "{}", printed);
+                    assert_eq!(evaluate(&printed, to), exec(&tree));
+                }
             }
-        );
+        }
+    }
+
+    /// Every tree up to the given depth over the number 2, so every operator
+    /// appears under every other one on either side.
+    fn all_trees(depth: u32) -> Vec<Tree<Token>> {
+        let span = Span::new(0, 0);
+        let leaf = Tree {
+            nodes: vec![],
+            value: Token::Term(2),
+            span,
+        };
+        if depth == 0 {
+            return vec![leaf];
+        }
+        let smaller = all_trees(depth - 1);
+        let node = |op, nodes| Tree {
+            nodes,
+            value: Token::Op(op),
+            span,
+        };
+        let mut trees = vec![leaf];
+        for tree in smaller.iter() {
+            trees.push(node(Operator::Neg, vec![tree.clone()]));
+        }
+        for op in [
+            Operator::Sum,
+            Operator::Sub,
+            Operator::Mul,
+            Operator::Div,
+            Operator::Pow,
+        ] {
+            for lhs in smaller.iter() {
+                for rhs in smaller.iter() {
+                    trees.push(node(op, vec![lhs.clone(), rhs.clone()]));
+                }
+            }
+        }
+        trees
+    }
+
+    #[test]
+    fn printed_trees_read_back() {
+        let tables = [Table::flat(), Table::addition_first(), Table::standard()];
+        let trees = all_trees(2);
+        assert_eq!(trees.len(), 1 + 7 + 5 * 7 * 7);
+        for tree in trees.iter() {
+            for table in tables.iter() {
+                let printed = tree.print(table);
+                assert_eq!(reparse(tree, table).sexp(), tree.sexp(), "{}", printed);
+                let value = exec(tree).map_err(|err| err.msg);
+                assert_eq!(evaluate(&printed, table).map_err(|err| err.msg), value);
+            }
+        }
+    }
+
+    #[test]
+    fn debug_views() {
+        let tree = parser(lexer("-2 * (3 + 4)").unwrap(), &Table::standard()).unwrap();
+        assert_eq!(tree.sexp(), "(* (neg 2) (+ 3 4))");
+        assert_eq!(tree.outline(), "*\n  neg\n    2\n  +\n    3\n    4");
     }
 }
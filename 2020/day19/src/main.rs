@@ -1,4 +1,8 @@
-use std::{collections::HashMap, fs, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    env, fmt, fs, process,
+    str::FromStr,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum Rule {
@@ -10,35 +14,42 @@ enum Rule {
 impl FromStr for Rule {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.chars().next() == Some('"') {
-            Ok(Rule::Term(
-                s.chars().skip(1).next().ok_or("no char")?.to_string(),
-            ))
+        let seq = |alt: &str| {
+            alt.split_whitespace()
+                .map(|id| {
+                    id.parse()
+                        .map_err(|_| format!("'{}' is not a rule number", id))
+                })
+                .collect::<Result<Vec<usize>, String>>()
+                .map(Rule::Seq)
+        };
+        if let Some(term) = s.strip_prefix('"') {
+            let term = term.strip_suffix('"').ok_or("unterminated string")?;
+            Ok(Rule::Term(term.to_string()))
         } else {
-            let rules: Vec<&str> = s.split("|").collect();
+            let rules: Vec<&str> = s.split('|').collect();
             if rules.len() > 1 {
-                let mut many = vec![];
-                for rule in rules {
-                    many.push(Rule::Seq(
-                        rule.trim()
-                            .split(" ")
-                            .map(|id| id.trim().parse().unwrap())
-                            .collect(),
-                    ));
-                }
-                Ok(Rule::Many(many))
-            } else {
-                Ok(Rule::Seq(
-                    s.trim()
-                        .split(" ")
-                        .map(|id| id.trim().parse().unwrap())
-                        .collect(),
+                Ok(Rule::Many(
+                    rules.into_iter().map(seq).collect::<Result<_, _>>()?,
                 ))
+            } else {
+                seq(s)
             }
         }
     }
 }
 
+impl Rule {
+    /// The sequences of symbols this rule can stand for.
+    fn alternatives(&self) -> Vec<Vec<Symbol>> {
+        match self {
+            Rule::Seq(seq) => vec![seq.iter().map(|&id| Symbol::Rule(id)).collect()],
+            Rule::Term(term) => vec![term.chars().map(Symbol::Term).collect()],
+            Rule::Many(rules) => rules.iter().flat_map(|rule| rule.alternatives()).collect(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 struct Grammar {
     rules: HashMap<usize, Rule>,
@@ -50,60 +61,523 @@ impl FromStr for Grammar {
         let mut grammar = Grammar {
             rules: HashMap::new(),
         };
-        for l in s.split("\n") {
-            let (id, v) = l.split_once(":").unwrap();
-            grammar.rules.insert(
-                id.trim().parse::<usize>().unwrap(),
-                v.trim().parse::<Rule>().unwrap(),
-            );
+        for (i, l) in s.split('\n').enumerate() {
+            let fail = |err| format!("line {}: {}", i + 1, err);
+            let (id, v) = l
+                .split_once(':')
+                .ok_or_else(|| fail(format!("expected '<id>: <rule>', found '{}'", l.trim())))?;
+            let id = id
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| fail(format!("'{}' is not a rule number", id.trim())))?;
+            grammar
+                .rules
+                .insert(id, v.trim().parse::<Rule>().map_err(fail)?);
         }
         Ok(grammar)
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Problem {
+    MissingStart,
+    Undefined(usize),
+    Unreachable,
+    Unproductive,
+    /// The rule can derive itself without reading anything first, along
+    /// the given rules.
+    LeftRecursive(Vec<usize>),
+}
+
+/// Something wrong with a rule of the grammar.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Finding {
+    rule: usize,
+    problem: Problem,
+}
+
+impl Finding {
+    /// Whether the grammar cannot be used at all. Unreachable rules are
+    /// dead weight, and left recursion is only a problem for matchers
+    /// other than the recogniser.
+    fn is_error(&self) -> bool {
+        !matches!(
+            self.problem,
+            Problem::Unreachable | Problem::LeftRecursive(_)
+        )
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.problem {
+            Problem::MissingStart => write!(f, "rule {}: start rule is not defined", self.rule),
+            Problem::Undefined(id) => {
+                write!(f, "rule {}: refers to undefined rule {}", self.rule, id)
+            }
+            Problem::Unreachable => {
+                write!(
+                    f,
+                    "rule {}: cannot be reached from the start rule",
+                    self.rule
+                )
+            }
+            Problem::Unproductive => write!(f, "rule {}: never derives a message", self.rule),
+            Problem::LeftRecursive(path) => {
+                let path: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+                write!(
+                    f,
+                    "rule {}: left recursive through {}",
+                    self.rule,
+                    path.join(" -> ")
+                )
+            }
+        }
+    }
+}
+
 impl Grammar {
-    fn validate(&self, s: &str) -> bool {
-        let sol = self.valid_rec(&self.rules[&0], s, 0, 0);
-        if sol.len() > 0 {
-            let len = sol[0].clone();
-            len == s.len()
-        } else {
-            false
+    /// Every alternative of every rule, by rule number.
+    fn productions(&self) -> Vec<Production> {
+        let mut ids: Vec<&usize> = self.rules.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .flat_map(|&lhs| {
+                self.rules[&lhs]
+                    .alternatives()
+                    .into_iter()
+                    .map(move |rhs| Production { lhs, rhs })
+            })
+            .collect()
+    }
+
+    /// Looks for undefined, unreachable, unproductive and left recursive
+    /// rules, in rule order.
+    fn lint(&self, start: usize) -> Vec<Finding> {
+        let prods = self.productions();
+        let mut findings = vec![];
+        if !self.rules.contains_key(&start) {
+            findings.push(Finding {
+                rule: start,
+                problem: Problem::MissingStart,
+            });
+        }
+        for prod in prods.iter() {
+            for sym in prod.rhs.iter() {
+                if let Symbol::Rule(id) = sym {
+                    if !self.rules.contains_key(id) {
+                        findings.push(Finding {
+                            rule: prod.lhs,
+                            problem: Problem::Undefined(*id),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut reachable = HashSet::new();
+        let mut todo = vec![start];
+        while let Some(id) = todo.pop() {
+            if self.rules.contains_key(&id) && reachable.insert(id) {
+                todo.extend(
+                    self.rules[&id]
+                        .alternatives()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|sym| match sym {
+                            Symbol::Rule(id) => Some(id),
+                            Symbol::Term(_) => None,
+                        }),
+                );
+            }
         }
+
+        // undefined rules are already reported, so they count as productive
+        // here to keep the rules using them out of the report
+        let defined_or =
+            |set: &HashSet<usize>, id: &usize| set.contains(id) || !self.rules.contains_key(id);
+        let productive = fixpoint(&prods, |set, sym| match sym {
+            Symbol::Rule(id) => defined_or(set, id),
+            Symbol::Term(_) => true,
+        });
+
+        let nullable = nullable(&prods);
+        let mut left_corners: HashMap<usize, Vec<usize>> = HashMap::new();
+        for prod in prods.iter() {
+            for sym in prod.rhs.iter() {
+                match sym {
+                    Symbol::Rule(id) if self.rules.contains_key(id) => {
+                        left_corners.entry(prod.lhs).or_default().push(*id);
+                        if !nullable.contains(id) {
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        let mut ids: Vec<usize> = self.rules.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            if !reachable.contains(&id) {
+                findings.push(Finding {
+                    rule: id,
+                    problem: Problem::Unreachable,
+                });
+            }
+            if !productive.contains(&id) {
+                findings.push(Finding {
+                    rule: id,
+                    problem: Problem::Unproductive,
+                });
+            }
+            if let Some(path) = cycle(&left_corners, id) {
+                findings.push(Finding {
+                    rule: id,
+                    problem: Problem::LeftRecursive(path),
+                });
+            }
+        }
+        findings.sort_by_key(|finding| finding.rule);
+        findings
     }
+}
+
+/// The rules with an alternative whose symbols all pass `holds`, growing the
+/// set until it settles.
+fn fixpoint(
+    prods: &[Production],
+    holds: impl Fn(&HashSet<usize>, &Symbol) -> bool,
+) -> HashSet<usize> {
+    let mut set = HashSet::new();
+    loop {
+        let before = set.len();
+        for prod in prods.iter() {
+            if prod.rhs.iter().all(|sym| holds(&set, sym)) {
+                set.insert(prod.lhs);
+            }
+        }
+        if set.len() == before {
+            return set;
+        }
+    }
+}
+
+/// The rules that can match the empty string.
+fn nullable(prods: &[Production]) -> HashSet<usize> {
+    fixpoint(prods, |set, sym| match sym {
+        Symbol::Rule(id) => set.contains(id),
+        Symbol::Term(_) => false,
+    })
+}
 
-    fn valid_rec(&self, rule: &Rule, s: &str, j: usize, lv: usize) -> Vec<usize> {
-        match rule {
-            Rule::Many(rules) => {
-                let mut conts = vec![];
-                for rule in rules {
-                    let sol = self.valid_rec(rule, s, j, lv + 1);
-                    conts.append(&mut sol.clone());
+/// The shortest path from `id` back to itself in `edges`, if any.
+fn cycle(edges: &HashMap<usize, Vec<usize>>, id: usize) -> Option<Vec<usize>> {
+    let mut parent: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([id]);
+    while let Some(at) = queue.pop_front() {
+        for &next in edges.get(&at).into_iter().flatten() {
+            if next == id {
+                let mut path = vec![id];
+                let mut back = at;
+                while back != id {
+                    path.push(back);
+                    back = parent[&back];
                 }
-                conts
-            }
-            Rule::Seq(seq) => {
-                let mut conts = vec![j];
-                let mut nextconts = vec![];
-                for step in seq {
-                    while conts.len() > 0 {
-                        let firstcont = conts.remove(0);
-                        let sol = self.valid_rec(&self.rules[step], s, firstcont, lv);
-                        nextconts.append(&mut sol.clone());
+                path.push(id);
+                path.reverse();
+                return Some(path);
+            }
+            if next != id && !parent.contains_key(&next) {
+                parent.insert(next, at);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Symbol {
+    Rule(usize),
+    Term(char),
+}
+
+/// One alternative of a rule: `lhs` derives the sequence `rhs`.
+#[derive(Debug, Clone)]
+struct Production {
+    lhs: usize,
+    rhs: Vec<Symbol>,
+}
+
+/// An Earley item: production `prod` read up to `dot`, starting at `origin`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+struct Item {
+    prod: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// Which rule matched which part of a message, `start..end` in characters.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct ParseTree {
+    rule: usize,
+    start: usize,
+    end: usize,
+    children: Vec<ParseTree>,
+}
+
+impl ParseTree {
+    /// One line per rule, indented under its parent, with the text it
+    /// matched.
+    fn render(&self, msg: &str) -> String {
+        let mut lines = vec![];
+        self.render_into(&msg.chars().collect::<Vec<char>>(), 0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn render_into(&self, msg: &[char], depth: usize, lines: &mut Vec<String>) {
+        lines.push(format!(
+            "{}{} [{}..{}] {}",
+            "  ".repeat(depth),
+            self.rule,
+            self.start,
+            self.end,
+            msg[self.start..self.end].iter().collect::<String>()
+        ));
+        for child in self.children.iter() {
+            child.render_into(msg, depth + 1, lines);
+        }
+    }
+}
+
+/// The chart of an Earley parse: the items alive at each position.
+struct Chart {
+    sets: Vec<Vec<Item>>,
+    seen: Vec<HashSet<Item>>,
+}
+
+impl Chart {
+    fn new(len: usize) -> Self {
+        Chart {
+            sets: vec![vec![]; len + 1],
+            seen: vec![HashSet::new(); len + 1],
+        }
+    }
+
+    fn add(&mut self, at: usize, item: Item) {
+        if self.seen[at].insert(item) {
+            self.sets[at].push(item);
+        }
+    }
+
+    fn contains(&self, at: usize, item: &Item) -> bool {
+        self.seen[at].contains(item)
+    }
+}
+
+/// A grammar compiled for an Earley recogniser, which copes with any
+/// context-free rule set, left recursion and empty alternatives included.
+#[derive(Debug)]
+struct Recogniser {
+    prods: Vec<Production>,
+    by_lhs: HashMap<usize, Vec<usize>>,
+    nullable: HashSet<usize>,
+    start: usize,
+}
+
+impl Recogniser {
+    fn new(grammar: &Grammar, start: usize) -> Result<Self, String> {
+        let prods = grammar.productions();
+        for prod in prods.iter() {
+            for sym in prod.rhs.iter() {
+                if let Symbol::Rule(id) = sym {
+                    if !grammar.rules.contains_key(id) {
+                        return Err(format!("rule {} refers to undefined rule {}", prod.lhs, id));
                     }
-                    conts = nextconts.clone();
-                    nextconts.clear();
                 }
-                conts
             }
-            Rule::Term(c) => {
-                if s.chars().nth(j) == c.chars().next() {
-                    vec![j + 1]
-                } else {
-                    vec![]
+        }
+        if !grammar.rules.contains_key(&start) {
+            return Err(format!("there is no start rule {}", start));
+        }
+
+        let mut by_lhs: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, prod) in prods.iter().enumerate() {
+            by_lhs.entry(prod.lhs).or_default().push(i);
+        }
+        let nullable = nullable(&prods);
+        Ok(Recogniser {
+            prods,
+            by_lhs,
+            nullable,
+            start,
+        })
+    }
+
+    fn chart(&self, msg: &[char]) -> Chart {
+        let mut chart = Chart::new(msg.len());
+        for &prod in self.by_lhs[&self.start].iter() {
+            chart.add(
+                0,
+                Item {
+                    prod,
+                    dot: 0,
+                    origin: 0,
+                },
+            );
+        }
+        for k in 0..=msg.len() {
+            let mut i = 0;
+            while i < chart.sets[k].len() {
+                let item = chart.sets[k][i];
+                i += 1;
+                let prod = &self.prods[item.prod];
+                let advanced = Item {
+                    dot: item.dot + 1,
+                    ..item
+                };
+                match prod.rhs.get(item.dot) {
+                    Some(&Symbol::Rule(id)) => {
+                        for &prod in self.by_lhs[&id].iter() {
+                            chart.add(
+                                k,
+                                Item {
+                                    prod,
+                                    dot: 0,
+                                    origin: k,
+                                },
+                            );
+                        }
+                        // an empty match completes right away, even if the
+                        // rule was predicted here before
+                        if self.nullable.contains(&id) {
+                            chart.add(k, advanced);
+                        }
+                    }
+                    Some(&Symbol::Term(c)) => {
+                        if msg.get(k) == Some(&c) {
+                            chart.add(k + 1, advanced);
+                        }
+                    }
+                    None => {
+                        let waiting: Vec<Item> = chart.sets[item.origin]
+                            .iter()
+                            .filter(|other| {
+                                self.prods[other.prod].rhs.get(other.dot)
+                                    == Some(&Symbol::Rule(prod.lhs))
+                            })
+                            .map(|other| Item {
+                                dot: other.dot + 1,
+                                ..*other
+                            })
+                            .collect();
+                        for other in waiting {
+                            chart.add(k, other);
+                        }
+                    }
                 }
             }
         }
+        chart
+    }
+
+    fn matches(&self, msg: &str) -> bool {
+        let msg: Vec<char> = msg.chars().collect();
+        let chart = self.chart(&msg);
+        chart.sets[msg.len()].iter().any(|item| {
+            let prod = &self.prods[item.prod];
+            prod.lhs == self.start && item.origin == 0 && item.dot == prod.rhs.len()
+        })
+    }
+
+    /// A parse tree of the whole message from the start rule, if it matches.
+    fn parse(&self, msg: &str) -> Option<ParseTree> {
+        let msg: Vec<char> = msg.chars().collect();
+        let chart = self.chart(&msg);
+        let mut building = HashSet::new();
+        self.build(&chart, &msg, self.start, 0, msg.len(), &mut building)
+    }
+
+    /// Finds a derivation of `msg[start..end]` from `rule` in the chart.
+    /// `building` holds the derivations under way further up, so that a
+    /// cycle through empty or unit rules is not followed forever.
+    fn build(
+        &self,
+        chart: &Chart,
+        msg: &[char],
+        rule: usize,
+        start: usize,
+        end: usize,
+        building: &mut HashSet<(usize, usize, usize)>,
+    ) -> Option<ParseTree> {
+        if !building.insert((rule, start, end)) {
+            return None;
+        }
+        let tree = self.by_lhs[&rule].iter().find_map(|&prod| {
+            let dot = self.prods[prod].rhs.len();
+            if !chart.contains(
+                end,
+                &Item {
+                    prod,
+                    dot,
+                    origin: start,
+                },
+            ) {
+                return None;
+            }
+            let children = self.children(chart, msg, prod, dot, start, end, building)?;
+            Some(ParseTree {
+                rule,
+                start,
+                end,
+                children,
+            })
+        });
+        building.remove(&(rule, start, end));
+        tree
+    }
+
+    /// Splits `msg[start..end]` among the first `dot` symbols of `prod`,
+    /// working back from the last one.
+    #[allow(clippy::too_many_arguments)]
+    fn children(
+        &self,
+        chart: &Chart,
+        msg: &[char],
+        prod: usize,
+        dot: usize,
+        start: usize,
+        end: usize,
+        building: &mut HashSet<(usize, usize, usize)>,
+    ) -> Option<Vec<ParseTree>> {
+        if dot == 0 {
+            return (start == end).then(Vec::new);
+        }
+        match self.prods[prod].rhs[dot - 1] {
+            Symbol::Term(c) => {
+                if end == start || msg[end - 1] != c {
+                    return None;
+                }
+                self.children(chart, msg, prod, dot - 1, start, end - 1, building)
+            }
+            Symbol::Rule(id) => (start..=end).rev().find_map(|mid| {
+                let before = Item {
+                    prod,
+                    dot: dot - 1,
+                    origin: start,
+                };
+                if !chart.contains(mid, &before) {
+                    return None;
+                }
+                let last = self.build(chart, msg, id, mid, end, building)?;
+                let mut children =
+                    self.children(chart, msg, prod, dot - 1, start, mid, building)?;
+                children.push(last);
+                Some(children)
+            }),
+        }
     }
 }
 
@@ -120,8 +594,8 @@ impl FromStr for Exercise {
         Ok(Exercise {
             grammar: rules.parse()?,
             words: msgs
-                .split("\n")
-                .filter(|x| x.len() > 0)
+                .split('\n')
+                .filter(|x| !x.is_empty())
                 .map(|l| l.trim().to_string())
                 .collect(),
         })
@@ -129,26 +603,107 @@ impl FromStr for Exercise {
 }
 
 fn main() {
-    let exercise: Exercise = fs::read_to_string("input.txt").unwrap().parse().unwrap();
-    let val = exercise
-        .words
-        .iter()
-        .filter(|w| exercise.grammar.validate(w))
-        .count();
-    println!("Valid words: {val}");
-    let ex_loop: Exercise = fs::read_to_string("input_2.txt").unwrap().parse().unwrap();
-    let val = ex_loop
-        .words
-        .iter()
-        .filter(|w| ex_loop.grammar.validate(w))
-        .count();
-    println!("Valid words: {val}");
+    let mut trees = false;
+    let mut paths = vec![];
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--tree" => trees = true,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        paths = vec!["input.txt".to_string(), "input_2.txt".to_string()];
+    }
+
+    for path in paths {
+        let exercise: Exercise = fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to open '{}'. {}", path, err))
+            .and_then(|raw| raw.parse())
+            .unwrap_or_else(|err| {
+                println!("{}", err);
+                process::exit(1);
+            });
+        let findings = exercise.grammar.lint(0);
+        for finding in findings.iter() {
+            let level = if finding.is_error() {
+                "error"
+            } else {
+                "warning"
+            };
+            println!("{}: {}: {}", path, level, finding);
+        }
+        if findings.iter().any(|finding| finding.is_error()) {
+            println!("Refusing to validate the messages of '{}'", path);
+            process::exit(1);
+        }
+        let recogniser = Recogniser::new(&exercise.grammar, 0).unwrap_or_else(|err| {
+            println!("Bad grammar in '{}': {}", path, err);
+            process::exit(1);
+        });
+        let mut val = 0;
+        for word in exercise.words.iter() {
+            if !trees {
+                val += recogniser.matches(word) as usize;
+            } else if let Some(tree) = recogniser.parse(word) {
+                val += 1;
+                println!("{}\n", tree.render(word));
+            }
+        }
+        println!("Valid words: {val}");
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::*;
 
+    /// The hand-written backtracking matcher, as a reference for the
+    /// recogniser on the puzzle inputs.
+    impl Grammar {
+        fn validate(&self, s: &str) -> bool {
+            let sol = self.valid_rec(&self.rules[&0], s, 0);
+            if !sol.is_empty() {
+                sol[0] == s.len()
+            } else {
+                false
+            }
+        }
+
+        fn valid_rec(&self, rule: &Rule, s: &str, j: usize) -> Vec<usize> {
+            match rule {
+                Rule::Many(rules) => {
+                    let mut conts = vec![];
+                    for rule in rules {
+                        let sol = self.valid_rec(rule, s, j);
+                        conts.extend(sol);
+                    }
+                    conts
+                }
+                Rule::Seq(seq) => {
+                    let mut conts = vec![j];
+                    let mut nextconts = vec![];
+                    for step in seq {
+                        while !conts.is_empty() {
+                            let firstcont = conts.remove(0);
+                            let sol = self.valid_rec(&self.rules[step], s, firstcont);
+                            nextconts.extend(sol);
+                        }
+                        conts = nextconts.clone();
+                        nextconts.clear();
+                    }
+                    conts
+                }
+                Rule::Term(c) => {
+                    if s.chars().nth(j) == c.chars().next() {
+                        vec![j + 1]
+                    } else {
+                        vec![]
+                    }
+                }
+            }
+        }
+    }
+
     const EX_TREE: &str = r#"0: 4 1 5
         1: 2 3 | 3 2
         2: 4 4 | 5 5
@@ -167,8 +722,8 @@ mod test {
         let answers: Vec<bool> = vec![true, false, true, false, false];
         let exercise: Exercise = EX_TREE.parse().unwrap();
         for (word, ans) in exercise.words.iter().zip(answers.iter()) {
-            dbg!(word, ans);
             assert_eq!(exercise.grammar.validate(word), *ans);
+            assert_eq!(recogniser(&exercise.grammar).matches(word), *ans);
         }
     }
 
@@ -230,8 +785,176 @@ mod test {
 
         let exercise: Exercise = EX_CYCLE.parse().unwrap();
         for (word, ans) in exercise.words.iter().zip(answers.iter()) {
-            dbg!(word, ans);
             assert_eq!(exercise.grammar.validate(word), *ans);
+            assert_eq!(recogniser(&exercise.grammar).matches(word), *ans);
         }
     }
+
+    fn recogniser(grammar: &Grammar) -> Recogniser {
+        Recogniser::new(grammar, 0).unwrap()
+    }
+
+    #[test]
+    fn oracle_on_puzzle_inputs() {
+        for path in ["input.txt", "input_2.txt"] {
+            let exercise: Exercise = fs::read_to_string(path).unwrap().parse().unwrap();
+            let recogniser = recogniser(&exercise.grammar);
+            for word in exercise.words.iter() {
+                assert_eq!(recogniser.matches(word), exercise.grammar.validate(word));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_tree() {
+        let exercise: Exercise = EX_TREE.parse().unwrap();
+        let tree = recogniser(&exercise.grammar).parse("ababbb").unwrap();
+        let leaf = |rule, start| ParseTree {
+            rule,
+            start,
+            end: start + 1,
+            children: vec![],
+        };
+        let pair = |rule, start, children| ParseTree {
+            rule,
+            start,
+            end: start + 2,
+            children,
+        };
+        assert_eq!(
+            tree,
+            ParseTree {
+                rule: 0,
+                start: 0,
+                end: 6,
+                children: vec![
+                    leaf(4, 0),
+                    ParseTree {
+                        rule: 1,
+                        start: 1,
+                        end: 5,
+                        children: vec![
+                            pair(3, 1, vec![leaf(5, 1), leaf(4, 2)]),
+                            pair(2, 3, vec![leaf(5, 3), leaf(5, 4)]),
+                        ],
+                    },
+                    leaf(5, 5),
+                ],
+            }
+        );
+        assert!(tree
+            .render("ababbb")
+            .starts_with("0 [0..6] ababbb\n  4 [0..1] a\n  1 [1..5] babb"));
+    }
+
+    #[test]
+    fn left_recursion_and_empty_rules() {
+        // 0 is a left recursive list of 1, where 1 is "a" or a bracketed 2
+        // and 2 may be empty
+        let grammar: Grammar = r#"0: 0 3 1 | 1
+            1: 4 | 5 2 6
+            2: 7 | 0
+            3: "+"
+            4: "a"
+            5: "("
+            6: ")"
+            7: """#
+            .parse()
+            .unwrap();
+        let recogniser = recogniser(&grammar);
+        for word in ["a", "a+a", "a+a+a", "()", "(a+())+a", "((a))"] {
+            assert!(recogniser.matches(word), "{}", word);
+        }
+        for word in ["", "+a", "a+", "(a", "a++a", "x"] {
+            assert!(!recogniser.matches(word), "{}", word);
+        }
+        for word in ["a+a+a", "(a+())+a", "()"] {
+            assert_eq!(recogniser.parse(word).is_some(), recogniser.matches(word));
+        }
+        let tree = recogniser.parse("a+a+a").unwrap();
+        assert_eq!((tree.children[0].rule, tree.children[0].end), (0, 3));
+    }
+
+    #[test]
+    fn grammar_errors() {
+        let grammar: Grammar = "0: 1 2\n1: \"a\"".parse().unwrap();
+        assert!(Recogniser::new(&grammar, 0).is_err());
+        assert!(Recogniser::new(&grammar, 3).is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "1 2 | 3".parse(),
+            Ok(Rule::Many(vec![Rule::Seq(vec![1, 2]), Rule::Seq(vec![3])]))
+        );
+        assert_eq!("".parse(), Ok(Rule::Seq(vec![])));
+        assert!("1 x".parse::<Rule>().is_err());
+        assert!("\"a".parse::<Rule>().is_err());
+        assert_eq!(
+            "0: 1\n1 \"a\"".parse::<Grammar>(),
+            Err("line 2: expected '<id>: <rule>', found '1 \"a\"'".to_string())
+        );
+        assert_eq!(
+            "0: 1\nx: \"a\"".parse::<Grammar>(),
+            Err("line 2: 'x' is not a rule number".to_string())
+        );
+        assert_eq!(
+            "0: 1 -2".parse::<Grammar>(),
+            Err("line 1: '-2' is not a rule number".to_string())
+        );
+    }
+
+    #[test]
+    fn lint() {
+        let findings = |raw: &str| raw.parse::<Grammar>().unwrap().lint(0);
+        let finding = |rule, problem| Finding { rule, problem };
+        assert_eq!(findings(EX_TREE.split_once("\n\n").unwrap().0), vec![]);
+        for path in ["input.txt", "input_2.txt"] {
+            let exercise: Exercise = fs::read_to_string(path).unwrap().parse().unwrap();
+            assert_eq!(exercise.grammar.lint(0), vec![]);
+        }
+
+        assert_eq!(
+            findings("1: \"a\""),
+            vec![
+                finding(0, Problem::MissingStart),
+                finding(1, Problem::Unreachable)
+            ]
+        );
+        assert_eq!(
+            findings("0: 1 2 | 3\n1: \"a\"\n4: 1"),
+            vec![
+                finding(0, Problem::Undefined(2)),
+                finding(0, Problem::Undefined(3)),
+                finding(4, Problem::Unreachable),
+            ]
+        );
+        // 2 only ever expands to itself, so 0 cannot finish either
+        assert_eq!(
+            findings("0: 1 2\n1: \"a\"\n2: 1 2"),
+            vec![
+                finding(0, Problem::Unproductive),
+                finding(2, Problem::Unproductive)
+            ]
+        );
+        // 3 can be empty, so 2 reaches 1 before reading anything
+        let found = findings("0: 1\n1: 2 4 | 5\n2: 3 1 | 2 5\n3: \"\"\n4: \"b\"\n5: \"a\"");
+        assert_eq!(
+            found,
+            vec![
+                finding(1, Problem::LeftRecursive(vec![1, 2, 1])),
+                finding(2, Problem::LeftRecursive(vec![2, 2])),
+            ]
+        );
+        assert!(found.iter().all(|finding| !finding.is_error()));
+        assert_eq!(
+            found[0].to_string(),
+            "rule 1: left recursive through 1 -> 2 -> 1"
+        );
+        assert_eq!(
+            finding(0, Problem::Undefined(2)).to_string(),
+            "rule 0: refers to undefined rule 2"
+        );
+    }
 }